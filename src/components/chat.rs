@@ -1,43 +1,164 @@
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+
+use gloo::events::EventListener;
+use gloo::timers::callback::{Interval, Timeout};
 use serde::{Deserialize, Serialize};
-use web_sys::HtmlInputElement;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{
+    DataTransfer, Element, File, HtmlAudioElement, HtmlElement, HtmlInputElement,
+    HtmlTextAreaElement, Node, ScrollBehavior, ScrollIntoViewOptions, ScrollLogicalPosition,
+    ScrollToOptions, Url,
+};
 use yew::prelude::*;
 use yew_agent::{Bridge, Bridged};
+use yew_router::prelude::*;
 
-use crate::services::event_bus::EventBus;
-use crate::{services::websocket::WebsocketService, User};
+use crate::components::context_menu::{ContextMenu, MenuItem};
+use crate::components::message_bubble::{message_element_id, MessageBubble};
+use crate::components::message_input::MessageInput;
+use crate::components::profile_card::ProfileCard;
+use crate::components::settings::{Settings, SettingsPanel};
+use crate::components::theme::{color_for, Theme};
+use crate::components::toast::{Toast, ToastKind};
+use crate::components::user_card::UserCard;
+use crate::services::commands::{self, Command};
+use crate::services::event_bus::{BusEvent, EventBus};
+use crate::services::export::{self, ExportFormat};
+use crate::services::giphy::{self, Gif, GiphyError};
+use crate::services::storage::{self, Stored};
+use crate::services::upload::{self, UploadError};
+use crate::services::websocket::{
+    ConnectionState, MessageData, MessageKind, MsgTypes, RosterEntry, SendStatus, WebSocketMessage,
+    WebsocketService, DEFAULT_ROOM,
+};
+use crate::services::{clipboard, emoji, filter, markdown, notifications};
+use crate::{avatar_url, Route, User, UserInner, AVATAR_STYLES};
 
+#[allow(clippy::enum_variant_names)]
 pub enum Msg {
-    HandleMsg(String),
+    HandleMsg(BusEvent),
     SubmitMessage,
+    InputChanged,
+    TypingExpired(String),
+    Scrolled,
+    ScrollToBottom,
+    Logout,
+    /// The tab is closing or navigating away.
+    PageUnload,
+    /// The browser went online or offline.
+    NetworkChanged,
+    JoinRoom(String),
+    ToggleMarkdown,
+    ToggleSound,
+    VisibilityChanged,
+    ToggleSearch,
+    ToggleTheme,
+    ToggleSidebar,
+    /// Empties the local history of the open conversation, after confirming.
+    ClearMessages,
+    ToggleExportMenu,
+    ToggleSettings,
+    UpdateSettings(Settings),
+    Export(ExportFormat),
+    RecallLastMessage,
+    StartEdit(String),
+    DeleteMessage(String),
+    ToggleReactionPicker(String),
+    ReplyTo(String),
+    DismissToast(u32),
+    CopyMessage(String),
+    /// Outcome of copying the message with this id: `true` if it reached the clipboard.
+    Copied(String, bool),
+    ClearCopied,
+    /// Opens the direct conversation with a user, or the room for `None`.
+    OpenConversation(Option<String>),
+    CancelReply,
+    /// Scrolls to a message and briefly highlights it, loading history first if needed.
+    ScrollToMessage(String),
+    ClearHighlight,
+    React {
+        id: String,
+        emoji: String,
+    },
+    CancelEdit,
+    MoveMentionSelection(i32),
+    CompleteMention(Option<usize>),
+    CloseMentions,
+    /// Periodic re-render so relative timestamps stay current.
+    Tick,
+    AvatarFailed(String),
+    ImageLoaded(String),
+    ImageFailed(String),
+    ToggleGifPicker,
+    SearchGifs,
+    GifResults(u32, Result<Vec<Gif>, GiphyError>),
+    SendGif(String),
+    /// Image files dropped on the message list or pasted into the input.
+    UploadImages(Vec<File>),
+    UploadFinished {
+        id: u32,
+        result: Result<String, UploadError>,
+    },
+    RetryUpload(u32),
+    DiscardUpload(u32),
+    /// Files are being dragged over the message list, or no longer are.
+    DragOver(bool),
+    ToggleEmojiPicker,
+    CloseEmojiPicker,
+    InsertEmoji(&'static str),
+    SearchInput,
+    ApplySearch,
+    ToggleGroupUsers,
+    /// Opens the profile card for a user at a viewport position.
+    ShowProfile {
+        name: String,
+        x: i32,
+        y: i32,
+    },
+    /// Closes the profile card after a short delay, unless the pointer
+    /// reaches the card (or returns to its trigger) first.
+    HideProfileSoon,
+    KeepProfile,
+    HideProfile,
+    /// Retries the connection now instead of waiting out the backoff.
+    Reconnect,
+    /// Mouse or keyboard input, already debounced.
+    Activity,
+    WentIdle,
+    ClearNewDivider,
+    /// Pins the message with this id, or unpins it if already pinned.
+    TogglePin(String),
+    TogglePinnedPanel,
+    /// Sends the failed message with this id again.
+    RetrySend(String),
+    /// Opens the action menu for a message at a viewport position.
+    OpenMessageMenu {
+        id: String,
+        x: i32,
+        y: i32,
+    },
+    CloseMessageMenu,
+    /// A message bubble received focus.
+    MessageFocused(String),
+    /// A key pressed in the message list: arrows, Home and End move between
+    /// messages, and Enter or the menu key open the focused one's actions.
+    MessageListKey(KeyboardEvent),
+    ToggleMute(String),
+    UnmuteAll,
+    HistoryTimedOut,
+    FocusInput,
+    /// Esc in the message box: clears it and gives up focus.
+    DismissInput,
 }
 
-#[derive(Deserialize)]
-struct MessageData {
-    from: String,
-    message: String,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum MsgTypes {
-    Users,
-    Register,
-    Message,
-}
-
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct WebSocketMessage {
-    message_type: MsgTypes,
-    data_array: Option<Vec<String>>,
-    data: Option<String>,
-}
-
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 struct UserProfile {
     name: String,
     avatar: String,
-    color: String,
+    /// Everyone in the `Users` list is connected; presence updates may clear this.
+    online: bool,
 }
 
 pub struct Chat {
@@ -45,154 +166,3973 @@ pub struct Chat {
     chat_input: NodeRef,
     _producer: Box<dyn Bridge<EventBus>>,
     wss: WebsocketService,
-    messages: Vec<MessageData>,
+    /// Oldest first, capped at `MAX_MESSAGES`.
+    messages: VecDeque<MessageData>,
+    /// Set while a request for older messages waits on the server; gives up
+    /// (treating the history as exhausted) if no answer arrives in time.
+    history_request: Option<Timeout>,
+    /// The server has nothing older for the current room.
+    history_exhausted: bool,
+    /// Distance from the bottom of the list to restore once older messages
+    /// have been prepended, so the view doesn't jump.
+    history_anchor: Option<i32>,
+    reconnect_attempt: Option<u32>,
+    user: User,
+    username: String,
+    /// Users currently typing, each with the timer that clears them again.
+    typing: HashMap<String, Timeout>,
+    /// Ids of messages that arrived in the last `ENTRANCE_MS`, with when, so
+    /// only they play the entrance animation and history never does.
+    arrived: HashMap<String, f64>,
+    /// Recent incoming messages as text for the screen-reader live region.
+    /// It's kept apart from the windowed list, whose rows come and go while
+    /// scrolling, so only genuinely new messages are read out.
+    announcements: VecDeque<(u64, String)>,
+    next_announcement_id: u64,
+    current_room: String,
+    settings: Settings,
+    export_menu_open: bool,
+    settings_open: bool,
+    /// Whether the sidebar drawer is open on small screens.
+    sidebar_open: bool,
+    /// Ids of pinned messages in the current room, oldest pin first. Pins are
+    /// shared with the room through `Pin` messages.
+    pinned: Vec<String>,
+    pinned_panel_open: bool,
+    /// User and viewport position of the open profile card.
+    profile_card: Option<(String, i32, i32)>,
+    profile_hide: Option<Timeout>,
+    /// Message id and viewport position of the open action menu.
+    message_menu: Option<(String, i32, i32)>,
+    /// Users whose messages are hidden locally; saved across sessions.
+    muted_users: HashSet<String>,
+    /// Browsers block audio until the user has interacted with the page.
+    sound_unlocked: bool,
+    /// Created on first use rather than up front.
+    notification_sound: Option<HtmlAudioElement>,
+    /// Page title before we started prefixing it with unread counts.
+    original_title: String,
+    /// Messages received while the tab was hidden.
+    hidden_unread: usize,
+    /// When the tab was last hidden. Messages from others after this get a
+    /// "new messages" divider until it is scrolled past or times out.
+    read_until: Option<f64>,
+    new_divider_timeout: Option<Timeout>,
+    _visibility_listener: EventListener,
+    /// Fires `WentIdle` unless reset by activity first.
+    idle_timer: Timeout,
+    /// We told the room we're away.
+    away: bool,
+    /// Users who reported being away, including us.
+    away_users: HashSet<String>,
+    _activity_listeners: Vec<EventListener>,
+    /// Document-level keyboard shortcuts, e.g. `/` to focus the message box.
+    _shortcut_listener: EventListener,
+    _unload_listener: EventListener,
+    /// The browser has no network. Kept apart from the socket's state: the
+    /// socket can drop while online, and the cached app shell still opens
+    /// offline, where retrying the socket is pointless until the network returns.
+    offline: bool,
+    _network_listeners: Vec<EventListener>,
+    search_open: bool,
+    search_input: NodeRef,
+    /// The query currently applied to the message list (lower-cased).
+    search_query: String,
+    search_debounce: Option<Timeout>,
+    _tick: Interval,
+    /// Avatar URLs that failed to load; these show initials instead.
+    broken_avatars: HashSet<String>,
+    /// Image URLs that finished loading or failed, to swap out placeholders.
+    loaded_images: HashSet<String>,
+    broken_images: HashSet<String>,
+    gif_picker_open: bool,
+    gif_input: NodeRef,
+    /// Bumped per search so late responses to older queries are ignored.
+    gif_search_seq: u32,
+    /// `None` until the first search finishes.
+    gif_results: Option<Result<Vec<Gif>, GiphyError>>,
+    gif_loading: bool,
+    /// Dropped or pasted images still on their way to the upload server.
+    uploads: Vec<PendingUpload>,
+    next_upload_id: u32,
+    drag_over: bool,
+    /// Wraps the emoji button and popover, so clicks inside don't close it.
+    emoji_picker: NodeRef,
+    /// Outside-click and Esc listeners, present only while the picker is open.
+    emoji_picker_listeners: Vec<EventListener>,
+    last_typing_sent: f64,
+    message_list: NodeRef,
+    /// Last known scroll offset and height of the message list, for windowing.
+    scroll_top: i32,
+    viewport_height: i32,
+    /// Whether the user is at (or near) the bottom of the message list.
+    stick_to_bottom: bool,
+    scroll_pending: bool,
+    /// Messages received while scrolled away from the bottom.
+    unread: usize,
+    /// Message id -> emoji -> users who reacted with it.
+    reactions: HashMap<String, BTreeMap<String, BTreeSet<String>>>,
+    /// The user we're in a direct conversation with; `None` shows the room.
+    dm_peer: Option<String>,
+    /// Histories of the conversations not on screen, keyed like `dm_peer`.
+    stashed: HashMap<Option<String>, VecDeque<MessageData>>,
+    /// Direct messages received while their conversation wasn't open.
+    dm_unread: HashMap<String, usize>,
+    send_limiter: RateLimiter,
+    /// Notices in the corner, oldest first, each removed by its own timer.
+    toasts: VecDeque<ToastEntry>,
+    next_toast_id: u32,
+    /// The last copied message and whether the clipboard accepted it.
+    copied: Option<(String, bool)>,
+    copied_reset: Option<Timeout>,
+    /// Message the next one we send replies to.
+    replying_to: Option<String>,
+    /// Message to bring into view once it has been rendered.
+    reveal_pending: Option<String>,
+    /// Message just jumped to, highlighted until its timer runs out.
+    highlighted: Option<String>,
+    highlight_reset: Option<Timeout>,
+    /// Message to jump to once loading history has brought it in.
+    jump_pending: Option<String>,
+    /// Message last focused in the list, where arrow keys continue from.
+    focused_message: Option<String>,
+    /// Message to move keyboard focus to once it has been rendered.
+    focus_pending: Option<String>,
+    /// The action menu was opened from the keyboard, so focus goes back to
+    /// its message when it closes.
+    menu_from_keyboard: bool,
+    /// Message whose reaction picker is open.
+    reaction_picker: Option<String>,
+    /// Id of our own message whose text is loaded into the input for editing.
+    editing: Option<String>,
+    /// The `@name` being typed: where its `@` sits (in UTF-16 units, like the
+    /// caret) and the partial name after it.
+    mention_token: Option<(u32, String)>,
+    /// Highlighted entry in the mention suggestions.
+    mention_selected: usize,
+    /// Character count of the text currently in the input.
+    draft_len: usize,
+    /// Set once the first user list arrives, which shows the connection works.
+    /// Until then a spinner covers the messages, and join/leave notices start after it.
+    ready: bool,
+}
+
+const ROOMS: [&str; 3] = [DEFAULT_ROOM, "random", "gifs"];
+/// Where muted users were kept before the storage layer, as a JSON array.
+const LEGACY_MUTED_USERS_KEY: &str = "mutedUsers";
+/// Drafts used to be kept one per key, as `draft:<room>` or `draft:@<user>`.
+const LEGACY_DRAFT_KEY_PREFIX: &str = "draft:";
+const NOTIFICATION_SOUND_SRC: &str = "notification.wav";
+/// Delay after the last keystroke before the search filter is re-applied.
+const SEARCH_DEBOUNCE_MS: u32 = 200;
+/// Undecodable server frames tolerated before a warning is shown.
+const MALFORMED_WARNING_THRESHOLD: usize = 5;
+/// Messages kept in memory; older ones are dropped as new ones arrive.
+const MAX_MESSAGES: usize = 1_000;
+/// Entries kept in the screen-reader live region.
+const MAX_ANNOUNCEMENTS: usize = 5;
+/// Length of the entrance animation in `static/index.html`.
+const ENTRANCE_MS: f64 = 250.0;
+/// The message box grows with its text up to this height, then scrolls.
+const MAX_INPUT_HEIGHT_PX: i32 = 160;
+/// Longest message, in characters, that may be sent.
+const MAX_MESSAGE_LEN: usize = 2_000;
+/// Bubble background for the current user's own messages.
+const OWN_BUBBLE_COLOR: &str = "#dbeafe";
+/// Most usernames offered while completing an `@mention`.
+const MENTION_SUGGESTION_LIMIT: usize = 6;
+/// At most `SEND_LIMIT` messages may be sent within `SEND_WINDOW_MS`.
+const SEND_LIMIT: usize = 5;
+const SEND_WINDOW_MS: f64 = 3_000.0;
+/// Grace period for moving the pointer from a user onto their profile card.
+const PROFILE_HIDE_DELAY_MS: u32 = 250;
+/// How long a toast stays up unless dismissed.
+const TOAST_MS: u32 = 5_000;
+/// Toasts on screen at once; the oldest goes first.
+const MAX_TOASTS: usize = 3;
+/// How long the "Copied!" confirmation stays up.
+const COPIED_NOTICE_MS: u32 = 2_000;
+/// How long a message jumped to from a quote stays highlighted.
+const HIGHLIGHT_MS: u32 = 1_500;
+/// How often relative timestamps are refreshed.
+const TICK_INTERVAL_MS: u32 = 30_000;
+/// Minimum gap between our own `Typing` broadcasts.
+const TYPING_DEBOUNCE_MS: f64 = 1_500.0;
+/// Most names listed in the typing notice before the rest are counted.
+const TYPING_NAMES_SHOWN: usize = 3;
+/// How long someone stays "typing" after their last broadcast.
+const TYPING_TIMEOUT_MS: u32 = 3_000;
+/// Round-trip times, in milliseconds, from which latency is shown as slow or bad.
+const SLOW_LATENCY_MS: f64 = 150.0;
+const HIGH_LATENCY_MS: f64 = 400.0;
+/// Distance from the bottom, in pixels, that still counts as "at the bottom".
+const STICK_THRESHOLD_PX: i32 = 48;
+/// Longest pause between two messages that still groups them under one header.
+const GROUP_GAP_MS: f64 = 5.0 * 60_000.0;
+/// Below this many messages the whole list is rendered.
+const VIRTUALIZE_THRESHOLD: usize = 150;
+/// Extra rows rendered above and below the viewport.
+const OVERSCAN_ROWS: usize = 10;
+/// Used until the message list has been measured.
+const DEFAULT_VIEWPORT_PX: i32 = 800;
+/// Inactivity after which we report ourselves as away.
+const IDLE_AFTER_MS: u32 = 5 * 60_000;
+/// Activity events closer together than this are handled once.
+const ACTIVITY_DEBOUNCE_MS: f64 = 5_000.0;
+const ACTIVITY_EVENTS: [&str; 5] = ["mousemove", "mousedown", "keydown", "touchstart", "wheel"];
+/// How long the "new messages" divider stays once the tab is visible again.
+const NEW_DIVIDER_LINGER_MS: u32 = 5_000;
+const NEW_DIVIDER_ID: &str = "new-messages-divider";
+/// Distance from the top, in pixels, at which older history is requested.
+const HISTORY_TRIGGER_PX: i32 = 64;
+/// How long to wait for a `History` answer; servers without history never send one.
+const HISTORY_TIMEOUT_MS: u32 = 10_000;
+
+/// Usernames whose messages are hidden.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(transparent)]
+struct MutedUsers(HashSet<String>);
+
+impl Stored for MutedUsers {
+    const NAME: &'static str = "mutedUsers";
+    const VERSION: u32 = 1;
+
+    fn legacy(storage: &web_sys::Storage) -> Option<Self> {
+        storage::take_legacy(storage, LEGACY_MUTED_USERS_KEY)
+            .and_then(|json| serde_json::from_str(&json).ok())
+    }
+}
+
+/// Unsent input, keyed by room name or `@<user>` for direct conversations.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(transparent)]
+struct Drafts(HashMap<String, String>);
+
+impl Stored for Drafts {
+    const NAME: &'static str = "drafts";
+    const VERSION: u32 = 1;
+
+    fn legacy(storage: &web_sys::Storage) -> Option<Self> {
+        let keys = storage::legacy_keys(storage, LEGACY_DRAFT_KEY_PREFIX);
+        let drafts: HashMap<String, String> = keys
+            .iter()
+            .filter_map(|key| {
+                Some((
+                    key[LEGACY_DRAFT_KEY_PREFIX.len()..].to_string(),
+                    storage::take_legacy(storage, key)?,
+                ))
+            })
+            .collect();
+        (!drafts.is_empty()).then_some(Drafts(drafts))
+    }
+}
+
+/// An image being uploaded, shown as a placeholder bubble until its link is sent.
+struct PendingUpload {
+    id: u32,
+    file: File,
+    /// Object URL of the file, for the placeholder's preview.
+    preview: String,
+    error: Option<UploadError>,
+}
+
+struct ToastEntry {
+    id: u32,
+    kind: ToastKind,
+    message: String,
+    _expire: Timeout,
+}
+
+/// Sliding-window limiter over the timestamps of recent sends.
+struct RateLimiter {
+    max: usize,
+    window_ms: f64,
+    sent: VecDeque<f64>,
+}
+
+impl RateLimiter {
+    fn new(max: usize, window_ms: f64) -> Self {
+        Self {
+            max,
+            window_ms,
+            sent: VecDeque::with_capacity(max),
+        }
+    }
+
+    /// Records a send at `now` and returns `true`, or returns `false` if
+    /// `max` sends already happened within the window.
+    fn try_acquire(&mut self, now: f64) -> bool {
+        while self
+            .sent
+            .front()
+            .is_some_and(|&t| now - t >= self.window_ms)
+        {
+            self.sent.pop_front();
+        }
+        if self.sent.len() >= self.max {
+            return false;
+        }
+        self.sent.push_back(now);
+        true
+    }
 }
 
 impl Chat {
-    fn parse_users(usernames: Option<Vec<String>>) -> Vec<UserProfile> {
-        let palette = vec![
-            "#fce4ec", "#e3f2fd", "#f3e5f5", "#e8f5e9", "#fff8e1", "#fbe9e7",
-            "#ede7f6", "#e0f7fa", "#f9fbe7", "#f1f8e9"
-        ];
+    /// Builds the sidebar list from a roster, reusing the current profile of
+    /// anyone whose avatar didn't change so their images aren't reloaded.
+    fn parse_users(&self, entries: Vec<RosterEntry>) -> Vec<UserProfile> {
+        let mut users: Vec<UserProfile> = entries
+            .into_iter()
+            .map(|u| {
+                let style = u.avatar_style.as_deref().unwrap_or(AVATAR_STYLES[0]);
+                let avatar = avatar_url(style, &u.name);
+                if let Some(existing) = self
+                    .users
+                    .iter()
+                    .find(|e| e.name == u.name && e.avatar == avatar)
+                {
+                    return existing.clone();
+                }
+                UserProfile {
+                    avatar,
+                    name: u.name,
+                    online: true,
+                }
+            })
+            .collect();
+        // Us first, then case-insensitive A–Z; the exact name breaks ties so
+        // the order never depends on what the server sent.
+        users.sort_by_cached_key(|u| {
+            (
+                u.name != self.username,
+                u.name.to_lowercase(),
+                u.name.clone(),
+            )
+        });
+        users
+    }
+
+    /// Background colour for `name` in the sidebar, cards and bubbles.
+    fn user_color(&self, name: &str) -> String {
+        color_for(name, self.settings.palette.colors())
+    }
 
-        usernames.unwrap_or_default().into_iter().enumerate().map(|(i, u)| {
-            UserProfile {
-                name: u.clone(),
-                avatar: format!("https://api.dicebear.com/9.x/pixel-art/svg?seed={}", u),
-                color: palette[i % palette.len()].to_string(),
+    fn push_message(&mut self, message: MessageData) {
+        let now = js_sys::Date::now();
+        self.arrived.retain(|_, at| now - *at < ENTRANCE_MS);
+        if let Some(id) = &message.id {
+            self.arrived.insert(id.clone(), now);
+        }
+        if message.from != self.username && !self.muted_users.contains(&message.from) {
+            self.announce(match message.kind {
+                MessageKind::User => format!("{}: {}", message.from, message.message),
+                MessageKind::Action => format!("{} {}", message.from, message.message),
+                MessageKind::System => message.message.clone(),
+            });
+        }
+        for evicted in push_capped(&mut self.messages, message, MAX_MESSAGES) {
+            if let Some(id) = evicted.id {
+                self.reactions.remove(&id);
             }
-        }).collect()
+        }
     }
-}
 
-impl Component for Chat {
-    type Message = Msg;
-    type Properties = ();
+    fn announce(&mut self, text: String) {
+        self.announcements
+            .push_back((self.next_announcement_id, text));
+        self.next_announcement_id += 1;
+        while self.announcements.len() > MAX_ANNOUNCEMENTS {
+            self.announcements.pop_front();
+        }
+    }
 
-    fn create(ctx: &Context<Self>) -> Self {
-        let (user, _) = ctx.link().context::<User>(Callback::noop()).expect("context to be set");
-        let wss = WebsocketService::new();
-        let username = user.username.borrow().clone();
+    /// Matches a server echo of our own message against the local copy, by id
+    /// when the server echoed one and by content otherwise.
+    fn confirm_pending(&mut self, echo: &MessageData) -> bool {
+        let local = self.messages.iter_mut().find(|m| {
+            m.status == SendStatus::Sending
+                && match (&m.id, &echo.id) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => m.message == echo.message,
+                }
+        });
+        match local {
+            Some(m) => {
+                m.status = SendStatus::Sent;
+                m.timestamp = echo.timestamp;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces the text of the message with `id`, marking it edited.
+    /// Returns whether such a message was found.
+    fn apply_edit(&mut self, id: &str, text: String) -> bool {
+        edit_by_id(&mut self.messages, id, text)
+    }
+
+    /// Marks our sent message with `id` as delivered, whichever conversation
+    /// it is in.
+    fn mark_delivered(&mut self, id: &str) -> bool {
+        let stashed = self
+            .stashed
+            .values_mut()
+            .flat_map(|messages| messages.iter_mut());
+        match self
+            .messages
+            .iter_mut()
+            .chain(stashed)
+            .find(|m| m.id.as_deref() == Some(id))
+        {
+            Some(m) if m.status == SendStatus::Sent && m.from == self.username => {
+                m.status = SendStatus::Delivered;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes the message with `id` if it was written by `from`, so nobody
+    /// can delete someone else's message. Returns whether one was removed.
+    fn remove_message(&mut self, id: &str, from: &str) -> bool {
+        match remove_by_id(&mut self.messages, id, from) {
+            Some(_) => {
+                self.reactions.remove(id);
+                self.pinned.retain(|p| p != id);
+                if self.editing.as_deref() == Some(id) {
+                    self.editing = None;
+                    self.clear_input();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_pinned(&mut self, id: String, pinned: bool) -> bool {
+        let present = self.pinned.contains(&id);
+        if pinned && !present {
+            self.pinned.push(id);
+        } else if !pinned && present {
+            self.pinned.retain(|p| *p != id);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Adds `user`'s `emoji` reaction to a message, or removes it if present.
+    fn toggle_reaction(&mut self, id: String, emoji: String, user: String) {
+        let by_emoji = self.reactions.entry(id.clone()).or_default();
+        let users = by_emoji.entry(emoji.clone()).or_default();
+        if !users.remove(&user) {
+            users.insert(user);
+        }
+        if users.is_empty() {
+            by_emoji.remove(&emoji);
+        }
+        if by_emoji.is_empty() {
+            self.reactions.remove(&id);
+        }
+    }
+
+    /// A short quote of the message `id` refers to, which scrolls to it on click.
+    fn render_quote(&self, ctx: &Context<Self>, id: &str) -> Html {
+        match self.messages.iter().find(|m| m.id.as_deref() == Some(id)) {
+            Some(original) => {
+                let target = id.to_string();
+                let onclick = ctx
+                    .link()
+                    .callback(move |_| Msg::ScrollToMessage(target.clone()));
+                html! {
+                    <button {onclick} title="Show original message" class="block w-full text-left text-xs mb-1 pl-2 border-l-2 border-gray-400 text-gray-600 hover:text-gray-900 truncate">
+                        <span class="font-semibold">{original.from.clone()}</span>{": "}{preview(&original.message)}
+                    </button>
+                }
+            }
+            // Possibly just older than what's loaded; clicking fetches history until it turns up.
+            None if self.dm_peer.is_none() && !self.history_exhausted => {
+                let target = id.to_string();
+                let onclick = ctx
+                    .link()
+                    .callback(move |_| Msg::ScrollToMessage(target.clone()));
+                html! {
+                    <button {onclick} title="Load earlier messages to find it" class="block w-full text-left text-xs mb-1 pl-2 border-l-2 border-gray-300 italic text-gray-500 hover:text-gray-900">
+                        {"Earlier message (click to load)"}
+                    </button>
+                }
+            }
+            None => html! {
+                <div class="text-xs mb-1 pl-2 border-l-2 border-gray-300 italic text-gray-500">{"Original message unavailable"}</div>
+            },
+        }
+    }
+
+    /// Reaction chips with counts, plus the picker if it's open for this message.
+    fn render_reactions(&self, ctx: &Context<Self>, id: &str) -> Html {
+        let chips = self.reactions.get(id).map(|by_emoji| {
+            by_emoji.iter().map(|(emoji, users)| {
+                let mine = users.contains(&self.username);
+                let who = users.iter().cloned().collect::<Vec<_>>().join(", ");
+                let (id, emoji) = (id.to_string(), emoji.clone());
+                let label = format!("{} {}", emoji, users.len());
+                let onclick = ctx.link().callback(move |_| Msg::React { id: id.clone(), emoji: emoji.clone() });
+                html! {
+                    <button {onclick} title={who} class={classes!("text-xs", "px-1.5", "rounded-full", "border", "bg-white/60",
+                        if mine { "border-blue-400" } else { "border-transparent" })}>
+                        {label}
+                    </button>
+                }
+            }).collect::<Html>()
+        });
+        let picker = if self.reaction_picker.as_deref() == Some(id) {
+            html! {
+                <div class="absolute z-10 -bottom-8 left-0 flex gap-1 p-1 rounded-full bg-white border shadow">
+                    { for emoji::REACTIONS.iter().map(|emoji| {
+                        let (id, emoji) = (id.to_string(), emoji.to_string());
+                        let label = emoji.clone();
+                        let onclick = ctx.link().callback(move |_| Msg::React { id: id.clone(), emoji: emoji.clone() });
+                        html! { <button {onclick} class="text-lg hover:scale-125 transition-transform">{label}</button> }
+                    }) }
+                </div>
+            }
+        } else {
+            html! {}
+        };
+
+        html! {
+            <>
+                { if let Some(chips) = chips { html! { <div class="flex flex-wrap gap-1 mt-1">{chips}</div> } } else { html! {} } }
+                {picker}
+            </>
+        }
+    }
+
+    /// Loads our message with `id` into the input; sending then edits it.
+    fn begin_edit(&mut self, id: String) -> bool {
+        let text = match self
+            .messages
+            .iter()
+            .find(|m| m.id.as_ref() == Some(&id) && m.from == self.username)
+        {
+            Some(m) => m.message.clone(),
+            None => return false,
+        };
+        let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() else {
+            return false;
+        };
+        input.set_value(&text);
+        self.fit_input();
+        let _ = input.focus();
+        let end = text.encode_utf16().count() as u32;
+        let _ = input.set_selection_range(end, end);
+        self.draft_len = text.chars().count();
+        self.editing = Some(id);
+        true
+    }
+
+    /// Sends a chat message to the open room or conversation and shows it as
+    /// sending until the server echoes it back, or as failed if it couldn't
+    /// be queued.
+    fn send_message(&mut self, text: String, kind: MessageKind) {
+        let mut message = MessageData {
+            from: self.username.clone(),
+            message: text,
+            timestamp: Some(js_sys::Date::now()),
+            kind,
+            id: Some(client_id()),
+            room: self.dm_peer.is_none().then(|| self.current_room.clone()),
+            status: SendStatus::Sending,
+            edited: false,
+            reply_to: self.replying_to.take(),
+        };
+        if !self.transmit(&message) {
+            message.status = SendStatus::Failed;
+        }
+        self.push_message(message);
+        self.scroll_pending = true;
+    }
 
+    /// Queues one of our messages on the socket, addressed to the open room or
+    /// conversation. Returns false if the socket wouldn't take it.
+    fn transmit(&self, m: &MessageData) -> bool {
         let message = WebSocketMessage {
-            message_type: MsgTypes::Register,
-            data: Some(username.to_string()),
-            data_array: None,
+            timestamp: m.timestamp,
+            id: m.id.clone(),
+            room: Some(self.current_room.clone()),
+            kind: (m.kind == MessageKind::Action).then_some(m.kind),
+            reply_to: m.reply_to.clone(),
+            ..WebSocketMessage::new(MsgTypes::Message, Some(m.message.clone()))
+        };
+        let message = match &self.dm_peer {
+            Some(peer) => WebSocketMessage {
+                message_type: MsgTypes::DirectMessage,
+                room: None,
+                from: Some(self.username.clone()),
+                to: Some(peer.clone()),
+                ..message
+            },
+            None => message,
         };
+        self.wss
+            .tx
+            .clone()
+            .try_send(serde_json::to_string(&message).unwrap())
+            .is_ok()
+    }
 
-        let _ = wss.tx.clone().try_send(serde_json::to_string(&message).unwrap());
+    /// Sizes the message box to its text, up to `MAX_INPUT_HEIGHT_PX`.
+    fn fit_input(&self) {
+        if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+            let style = input.style();
+            // Collapse first so the box can shrink as well as grow.
+            let _ = style.set_property("height", "auto");
+            let height = input.scroll_height().min(MAX_INPUT_HEIGHT_PX);
+            let _ = style.set_property("height", &format!("{}px", height));
+        }
+    }
 
-        Self {
-            users: vec![],
-            messages: vec![],
-            chat_input: NodeRef::default(),
-            wss,
-            _producer: EventBus::bridge(ctx.link().callback(Msg::HandleMsg)),
+    /// Empties the input along with its saved draft.
+    fn clear_input(&mut self) {
+        if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+            input.set_value("");
         }
+        self.fit_input();
+        self.save_draft("");
+        self.draft_len = 0;
+        self.mention_token = None;
     }
 
-    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
-        match msg {
-            Msg::HandleMsg(s) => {
-                if let Ok(msg) = serde_json::from_str::<WebSocketMessage>(&s) {
-                    match msg.message_type {
-                        MsgTypes::Users => {
-                            self.users = Self::parse_users(msg.data_array);
-                            true
-                        },
-                        MsgTypes::Message => {
-                            if let Some(raw) = msg.data {
-                                if let Ok(message_data) = serde_json::from_str(&raw) {
-                                    self.messages.push(message_data);
-                                    return true;
-                                }
-                            }
-                            false
-                        },
-                        _ => false,
-                    }
-                } else {
-                    false
-                }
+    fn draft_key(&self) -> String {
+        match &self.dm_peer {
+            Some(peer) => format!("@{}", peer),
+            None => self.current_room.clone(),
+        }
+    }
+
+    /// Puts the on-screen history away and brings up the one for `peer`
+    /// (`None` for the room), so everything else keeps using `self.messages`.
+    fn switch_conversation(&mut self, peer: Option<String>) {
+        let shown = self.stashed.remove(&peer).unwrap_or_default();
+        let hidden = std::mem::replace(&mut self.messages, shown);
+        self.stashed
+            .insert(std::mem::replace(&mut self.dm_peer, peer), hidden);
+        if let Some(peer) = &self.dm_peer {
+            self.dm_unread.remove(peer);
+        }
+        self.editing = None;
+        self.replying_to = None;
+        self.reaction_picker = None;
+        self.unread = 0;
+        self.stick_to_bottom = true;
+        self.scroll_pending = true;
+        self.history_request = None;
+        self.history_exhausted = false;
+        self.history_anchor = None;
+        self.jump_pending = None;
+        self.restore_draft();
+    }
+
+    /// Asks the server for messages older than the oldest one shown. Returns
+    /// whether a request went out.
+    fn request_history(&mut self, ctx: &Context<Self>) -> bool {
+        if self.history_request.is_some()
+            || self.history_exhausted
+            || self.dm_peer.is_some()
+            || !self.wss.is_connected()
+        {
+            return false;
+        }
+        let Some(oldest) = self.messages.iter().find(|m| m.kind != MessageKind::System) else {
+            return false;
+        };
+        let message = WebSocketMessage {
+            id: oldest.id.clone(),
+            timestamp: oldest.timestamp,
+            room: Some(self.current_room.clone()),
+            ..WebSocketMessage::new(MsgTypes::History, None)
+        };
+        if self
+            .wss
+            .tx
+            .clone()
+            .try_send(serde_json::to_string(&message).unwrap())
+            .is_err()
+        {
+            return false;
+        }
+        let link = ctx.link().clone();
+        self.history_request = Some(Timeout::new(HISTORY_TIMEOUT_MS, move || {
+            link.send_message(Msg::HistoryTimedOut)
+        }));
+        true
+    }
+
+    /// Puts a batch of older messages in front of the current ones, skipping
+    /// any we already have and stopping at `MAX_MESSAGES`.
+    fn prepend_history(&mut self, messages: Vec<MessageData>) {
+        let known: HashSet<String> = self.messages.iter().filter_map(|m| m.id.clone()).collect();
+        let mut older: Vec<MessageData> = messages
+            .into_iter()
+            .filter(|m| m.id.as_ref().is_none_or(|id| !known.contains(id)))
+            .collect();
+        let room_left = MAX_MESSAGES.saturating_sub(self.messages.len());
+        if older.is_empty() || room_left == 0 {
+            self.history_exhausted = true;
+            return;
+        }
+        if older.len() > room_left {
+            older.drain(..older.len() - room_left);
+        }
+
+        if let Some(list) = self.message_list.cast::<Element>() {
+            self.history_anchor = Some(list.scroll_height() - list.scroll_top());
+        }
+        // Keep the windowed slice on the rows that were on screen until the
+        // real scroll position is restored.
+        if self.messages.len() + older.len() > VIRTUALIZE_THRESHOLD {
+            self.scroll_top += older.len() as i32 * self.settings.density.estimated_row_px();
+        }
+        for message in older.into_iter().rev() {
+            self.messages.push_front(message);
+        }
+    }
+
+    fn save_muted_users(&self) {
+        let _ = storage::set(&MutedUsers(self.muted_users.clone()));
+    }
+
+    /// Saves the settings, warning once if the browser won't keep them.
+    fn save_settings(&mut self, ctx: &Context<Self>) {
+        if let Err(error) = self.settings.save() {
+            self.show_toast(
+                ctx,
+                ToastKind::Warning,
+                format!("Settings won't be remembered: {}.", error),
+            );
+        }
+    }
+
+    /// Adds a message to a conversation that isn't on screen.
+    fn stash_message(&mut self, peer: Option<String>, message: MessageData) {
+        push_capped(self.stashed.entry(peer).or_default(), message, MAX_MESSAGES);
+    }
+
+    /// Stores the unsent input for the current room, dropping it once empty.
+    fn save_draft(&self, text: &str) {
+        let Drafts(mut drafts) = storage::get();
+        if text.is_empty() {
+            drafts.remove(&self.draft_key());
+        } else {
+            drafts.insert(self.draft_key(), text.to_string());
+        }
+        let _ = storage::set(&Drafts(drafts));
+    }
+
+    /// Puts the current room's saved draft (or nothing) back into the input.
+    fn restore_draft(&mut self) {
+        let Drafts(mut drafts) = storage::get();
+        let draft = drafts.remove(&self.draft_key()).unwrap_or_default();
+        if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+            input.set_value(&draft);
+            self.draft_len = draft.chars().count();
+        }
+        self.fit_input();
+    }
+
+    /// Marks a message as just jumped to, for `HIGHLIGHT_MS`.
+    fn highlight(&mut self, ctx: &Context<Self>, id: String) {
+        self.highlighted = Some(id);
+        let link = ctx.link().clone();
+        self.highlight_reset = Some(Timeout::new(HIGHLIGHT_MS, move || {
+            link.send_message(Msg::ClearHighlight)
+        }));
+    }
+
+    /// Shows a notice for `TOAST_MS`. Repeating the newest one restarts its
+    /// timer instead of stacking a copy.
+    fn show_toast(&mut self, ctx: &Context<Self>, kind: ToastKind, message: String) {
+        if self
+            .toasts
+            .back()
+            .is_some_and(|t| t.kind == kind && t.message == message)
+        {
+            self.toasts.pop_back();
+        }
+        let id = self.next_toast_id;
+        self.next_toast_id = self.next_toast_id.wrapping_add(1);
+        let link = ctx.link().clone();
+        self.toasts.push_back(ToastEntry {
+            id,
+            kind,
+            message,
+            _expire: Timeout::new(TOAST_MS, move || link.send_message(Msg::DismissToast(id))),
+        });
+        while self.toasts.len() > MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+    }
+
+    fn idle_timer(ctx: &Context<Self>) -> Timeout {
+        let link = ctx.link().clone();
+        Timeout::new(IDLE_AFTER_MS, move || link.send_message(Msg::WentIdle))
+    }
+
+    /// Tells everyone whether we're away; only called on transitions.
+    fn send_presence(&self, away: bool) {
+        let message = WebSocketMessage {
+            from: Some(self.username.clone()),
+            ..WebSocketMessage::new(
+                MsgTypes::Presence,
+                Some(if away { "away" } else { "active" }.to_string()),
+            )
+        };
+        let _ = self
+            .wss
+            .tx
+            .clone()
+            .try_send(serde_json::to_string(&message).unwrap());
+    }
+
+    /// Whether the "new messages" divider has scrolled out of view above the list.
+    fn new_divider_scrolled_past(&self) -> bool {
+        let divider = gloo::utils::document().get_element_by_id(NEW_DIVIDER_ID);
+        match (divider, self.message_list.cast::<Element>()) {
+            (Some(divider), Some(list)) => {
+                divider.get_bounding_client_rect().bottom() < list.get_bounding_client_rect().top()
             }
-            Msg::SubmitMessage => {
-                if let Some(input) = self.chat_input.cast::<HtmlInputElement>() {
-                    let text = input.value().trim().to_string();
-                    if !text.is_empty() {
-                        let message = WebSocketMessage {
-                            message_type: MsgTypes::Message,
-                            data: Some(text),
-                            data_array: None,
-                        };
-                        let _ = self.wss.tx.clone().try_send(serde_json::to_string(&message).unwrap());
-                        input.set_value("");
-                    }
+            _ => false,
+        }
+    }
+
+    fn update_title(&self) {
+        let title = if self.hidden_unread > 0 {
+            format!("({}) {}", self.hidden_unread, self.original_title)
+        } else {
+            self.original_title.clone()
+        };
+        gloo::utils::document().set_title(&title);
+    }
+
+    fn play_notification_sound(&mut self) {
+        if self.settings.sound_muted || !self.sound_unlocked {
+            return;
+        }
+        if self.notification_sound.is_none() {
+            self.notification_sound = HtmlAudioElement::new_with_src(NOTIFICATION_SOUND_SRC).ok();
+        }
+        if let Some(audio) = &self.notification_sound {
+            audio.set_current_time(0.0);
+            let _ = audio.play();
+        }
+    }
+
+    /// Whether `m` just arrived and should slide in.
+    fn animates(&self, m: &MessageData) -> bool {
+        !self.settings.reduce_motion
+            && m.id
+                .as_ref()
+                .and_then(|id| self.arrived.get(id))
+                .is_some_and(|at| js_sys::Date::now() - at < ENTRANCE_MS)
+    }
+
+    /// "Not sent" with a retry button, for messages that failed to go out.
+    fn render_retry(&self, ctx: &Context<Self>, m: &MessageData) -> Html {
+        match &m.id {
+            Some(id) if m.status == SendStatus::Failed => {
+                let id = id.clone();
+                let onclick = ctx.link().callback(move |_| Msg::RetrySend(id.clone()));
+                html! {
+                    <button {onclick} title="Send again" class="not-italic text-xs font-semibold text-red-600 hover:underline">{"⟳ Not sent, retry"}</button>
                 }
-                false
             }
+            _ => html! {},
         }
     }
 
-    fn view(&self, ctx: &Context<Self>) -> Html {
-        let submit = ctx.link().callback(|_| Msg::SubmitMessage);
+    /// `grouped` messages follow one from the same sender and drop the avatar and name.
+    fn render_message(
+        &self,
+        ctx: &Context<Self>,
+        m: &MessageData,
+        is_own: bool,
+        grouped: bool,
+    ) -> Html {
+        if m.kind == MessageKind::System {
+            return html! {
+                <div class={classes!("text-center", "text-xs", "italic", "my-2", self.settings.theme.muted())}>{m.message.clone()}</div>
+            };
+        }
+        let onfocus = m.id.clone().map(|id| {
+            ctx.link()
+                .callback(move |_| Msg::MessageFocused(id.clone()))
+        });
+        let highlighted = m.id.is_some() && self.highlighted == m.id;
+        if m.kind == MessageKind::Action {
+            return html! {
+                <div id={m.id.as_deref().map(message_element_id)} tabindex="-1" {onfocus} role="article"
+                    class={classes!("text-sm", "italic", "mx-4", "my-2", "rounded", "focus:outline-none", "focus:ring-2", "focus:ring-blue-500", "transition-colors", is_own.then_some("text-right"), (m.status == SendStatus::Sending).then_some("opacity-60"), highlighted.then_some("bg-yellow-100"))}>
+                    {"* "}<span class="font-semibold">{highlight(&m.from, &self.search_query)}</span>{" "}<span class="whitespace-pre-wrap break-words">{self.render_text(&m.message)}</span>
+                    {" "}{self.render_retry(ctx, m)}
+                </div>
+            };
+        }
+
+        let user_opt = self.users.iter().find(|u| u.name == m.from);
+        let avatar = user_opt
+            .map(|u| u.avatar.clone())
+            .unwrap_or_else(|| avatar_url(AVATAR_STYLES[0], "unknown"));
+        let color = if is_own {
+            OWN_BUBBLE_COLOR.to_string()
+        } else {
+            self.user_color(&m.from)
+        };
+        let avatar = match user_opt {
+            _ if grouped => html! {},
+            Some(user) => html! {
+                <div onmouseenter={self.profile_trigger(ctx, &user.name)} onmouseleave={ctx.link().callback(|_| Msg::HideProfileSoon)}>
+                    {self.render_avatar(ctx, &avatar, &m.from, &color, self.settings.density.avatar_size())}
+                </div>
+            },
+            None => self.render_avatar(
+                ctx,
+                &avatar,
+                &m.from,
+                &color,
+                self.settings.density.avatar_size(),
+            ),
+        };
+        let mentions_me =
+            !is_own && extract_mentions(&m.message, &self.users).contains(&self.username);
+
+        let oncontextmenu = m.id.clone().map(|id| {
+            ctx.link().callback(move |e: MouseEvent| {
+                e.prevent_default();
+                Msg::OpenMessageMenu {
+                    id: id.clone(),
+                    x: e.client_x(),
+                    y: e.client_y(),
+                }
+            })
+        });
+
+        let quote = m
+            .reply_to
+            .as_deref()
+            .map(|id| self.render_quote(ctx, id))
+            .unwrap_or_default();
+        let body = html! {
+            <div class="text-xs text-gray-800">
+                { if is_image_url(&m.message) {
+                    self.render_image(ctx, &m.message)
+                } else {
+                    // Pre-wrap keeps pasted line breaks and indentation; break-words stops long lines overflowing the bubble.
+                    html! { <div id={m.id.as_deref().map(message_text_element_id)} class="whitespace-pre-wrap break-words">{self.render_text(&m.message)}</div> }
+                } }
+            </div>
+        };
 
         html! {
-            <div class="flex w-screen bg-gradient-to-br from-blue-50 to-pink-50">
-                <div class="flex-none w-56 h-screen bg-amber-25 overflow-y-auto backdrop-blur">
-                    <div class="text-xl px-3 pt-3 pb-3.5 font-semibold bg-amber-200 border-l-2 border-b-2 border-amber-300">{"Users"}</div>
+            <MessageBubble message={m.clone()} {avatar} {color} {is_own} {grouped} density={self.settings.density} {mentions_me} {highlighted} animate={self.animates(m)} {onfocus}
+                sender={highlight(&m.from, &self.search_query)} {oncontextmenu} {quote} {body}>
+                { m.id.as_deref().map(|id| self.render_reactions(ctx, id)).unwrap_or_default() }
+                <div class="flex items-center justify-end gap-1 text-[10px] text-gray-500 mt-1">
                     {
-                        self.users.clone().iter().map(|u| {
-                            html!{
-                                <div class="flex m-3 rounded-lg p-2 border-1 border-amber-300" style={format!("background-color:{}", u.color)}>
-                                    <div>
-                                        <img class="w-12 h-12 rounded-full hover:scale-110 hover:brightness-125 transition-transform duration-300" src={u.avatar.clone()} alt="avatar"/>
-                                    </div>
-                                    <div class="flex-grow p-3">
-                                        <div class="flex text-xs justify-between">
-                                            <div class="font-semibold">{u.name.clone()}</div>
-                                        </div>
-                                        <div class="text-xs text-gray-600">{"Hi there!"}</div>
-                                    </div>
-                                </div>
+                        if let Some(id) = &m.id {
+                            let menu_id = id.clone();
+                            let on_menu = ctx.link().callback(move |e: MouseEvent| {
+                                let rect = e.target_unchecked_into::<Element>().get_bounding_client_rect();
+                                Msg::OpenMessageMenu { id: menu_id.clone(), x: rect.left() as i32, y: rect.bottom() as i32 }
+                            });
+                            html! {
+                                <>
+                                    {
+                                        match &self.copied {
+                                            Some((copied, true)) if copied == id => html! { <span class="text-green-700">{"Copied!"}</span> },
+                                            Some((copied, false)) if copied == id => html! { <span>{"Selected, press Ctrl+C"}</span> },
+                                            _ => html! {},
+                                        }
+                                    }
+                                    <button onclick={on_menu} title="More actions" aria-label="More actions" aria-haspopup="menu" class="hidden group-hover:inline px-1 text-xs leading-none hover:text-gray-800">{"⋯"}</button>
+                                </>
                             }
-                        }).collect::<Html>()
+                        } else {
+                            html! {}
+                        }
                     }
-                </div>
-                <div class="grow h-screen flex flex-col">
-                    <div class="w-full h-14 border-b-2 border-pink-300 border-l-2 bg-pink-200"><div class="text-xl p-3 font-semibold">{"UwU Cafee Chat"}</div></div>
-                    <div class="w-full grow overflow-auto border-l-1 border-b-2 border-pink-300 bg-pink-50 px-4 py-2">
-                        {
-                            self.messages.iter().map(|m| {
-                                let user_opt = self.users.iter().find(|u| u.name == m.from);
-                                let (avatar, color) = user_opt
-                                    .map(|u| (u.avatar.clone(), u.color.clone()))
-                                    .unwrap_or_else(|| ("https://api.dicebear.com/9.x/pixel-art/svg?seed=unknown".to_string(), "#ffffff".to_string()));
-                                html! {
-                                    <div class="flex items-end max-w-md m-4 rounded-tl-[0.25rem] rounded-tr-[1rem] rounded-br-[1rem] border" style={format!("background-color:{}; border-color:{}", color, color)}>
-                                        <img class="w-8 h-8 rounded-full m-3" src={avatar} alt="avatar"/>
-                                        <div class="p-3">
-                                            <div class="text-sm font-semibold">{m.from.clone()}</div>
-                                            <div class="text-xs text-gray-800">
-                                                { if m.message.ends_with(".gif") {
-                                                    html! { <img class="mt-3" src={m.message.clone()} /> }
-                                                } else {
-                                                    html! { <span>{m.message.clone()}</span> }
-                                                } }
-                                            </div>
-                                        </div>
-                                    </div>
-                                }
-                            }).collect::<Html>()
+                    {self.render_retry(ctx, m)}
+                    { if m.id.as_ref().is_some_and(|id| self.pinned.contains(id)) { html! { <span title="Pinned">{"📌"}</span> } } else { html! {} } }
+                    { if m.edited { html! { <span>{"(edited)"}</span> } } else { html! {} } }
+                    {
+                        if let Some(ts) = m.timestamp {
+                            html! { <span title={format_full_date(ts, self.settings.hour12)}>{format_relative(ts, js_sys::Date::now())}</span> }
+                        } else {
+                            html! {}
                         }
-                    </div>
-                    <div class="w-full h-14 flex px-3 items-center bg-pink-200 border-pink-300 border-l-2 backdrop-blur">
-                        <input ref={self.chat_input.clone()} type="text" placeholder="Message" class="bg-white text-gray-700 border border-pink-300 focus:border-blue-400 focus:ring-2 focus:ring-blue-200 rounded-full px-4 py-2 transition-all duration-300 w-full placeholder-gray-500" name="message" required=true />
-                        <button onclick={submit} class="ml-3 transition-transform hover:scale-110 active:translate-x-1 bg-pink-500 hover:bg-pink-600 text-white p-2 rounded-full">
-                            <svg class="w-5 h-5 fill-current" viewBox="0 0 24 24"><path d="M2.01 21L23 12 2.01 3 2 10l15 2-15 2z"/></svg>
-                        </button>
-                    </div>
+                    }
+                    {
+                        match m.status {
+                            _ if !is_own => html! {},
+                            SendStatus::Sent => html! { <span title="Sent">{"✓"}</span> },
+                            SendStatus::Delivered => html! { <span title="Delivered" class="text-blue-600">{"✓✓"}</span> },
+                            SendStatus::Sending | SendStatus::Failed => html! {},
+                        }
+                    }
                 </div>
+            </MessageBubble>
+        }
+    }
+
+    /// Opens `name`'s profile card beside the element the pointer entered.
+    fn profile_trigger(&self, ctx: &Context<Self>, name: &str) -> Callback<MouseEvent> {
+        let name = name.to_string();
+        ctx.link().callback(move |e: MouseEvent| {
+            let rect = e
+                .target_unchecked_into::<Element>()
+                .get_bounding_client_rect();
+            Msg::ShowProfile {
+                name: name.clone(),
+                x: rect.right() as i32 + 8,
+                y: rect.top() as i32,
+            }
+        })
+    }
+
+    fn render_profile_card(&self, ctx: &Context<Self>) -> Html {
+        let Some((name, x, y)) = &self.profile_card else {
+            return html! {};
+        };
+        let Some(user) = self.users.iter().find(|u| &u.name == name) else {
+            return html! {};
+        };
+        let is_self = user.name == self.username;
+        let status = match (user.online, self.away_users.contains(&user.name)) {
+            (false, _) => "Offline",
+            (true, true) => "Away",
+            (true, false) => "Online",
+        };
+        let status = if is_self {
+            format!("{} · you", status)
+        } else {
+            status.to_string()
+        };
+        let color = self.user_color(&user.name);
+        let on_message = (!is_self).then(|| {
+            let peer = user.name.clone();
+            ctx.link()
+                .callback(move |_| Msg::OpenConversation(Some(peer.clone())))
+        });
+        html! {
+            <ProfileCard key={user.name.clone()} name={user.name.clone()} avatar={self.render_avatar(ctx, &user.avatar, &user.name, &color, "w-16 h-16")}
+                {color} {status} x={*x} y={*y} {on_message}
+                onmouseenter={ctx.link().callback(|_| Msg::KeepProfile)} onmouseleave={ctx.link().callback(|_| Msg::HideProfileSoon)} />
+        }
+    }
+
+    /// Offered while waiting to reconnect; disabled during the attempt itself.
+    fn render_reconnect_button(&self, ctx: &Context<Self>) -> Html {
+        let connecting = self.wss.state() == ConnectionState::Connecting;
+        html! {
+            <button onclick={ctx.link().callback(|_| Msg::Reconnect)} disabled={connecting}
+                class="text-xs font-semibold px-2 py-0.5 rounded-full bg-pink-500 hover:bg-pink-600 text-white disabled:opacity-50">
+                { if connecting { "Connecting…" } else { "Reconnect now" } }
+            </button>
+        }
+    }
+
+    /// The collapsible "Pinned" strip above the message list; hidden when
+    /// none of the pinned messages is loaded.
+    fn render_pinned(&self, ctx: &Context<Self>) -> Html {
+        let pinned: Vec<&MessageData> = self
+            .pinned
+            .iter()
+            .filter_map(|id| self.messages.iter().find(|m| m.id.as_ref() == Some(id)))
+            .collect();
+        if pinned.is_empty() {
+            return html! {};
+        }
+        let theme = self.settings.theme;
+        let toggle = ctx.link().callback(|_| Msg::TogglePinnedPanel);
+        html! {
+            <div class={classes!("w-full", "px-4", "py-1", "border-l-2", "border-b", "text-xs", theme.header())}>
+                <button onclick={toggle} class="font-semibold py-1">
+                    {format!("{} 📌 Pinned ({})", if self.pinned_panel_open { "▾" } else { "▸" }, pinned.len())}
+                </button>
+                {
+                    if self.pinned_panel_open {
+                        html! {
+                            <ul class="max-h-32 overflow-y-auto pb-1">
+                                { for pinned.iter().map(|m| {
+                                    let id = m.id.clone().unwrap_or_default();
+                                    let target = id.clone();
+                                    let show = ctx.link().callback(move |_| Msg::ScrollToMessage(target.clone()));
+                                    let unpin = ctx.link().callback(move |_| Msg::TogglePin(id.clone()));
+                                    html! {
+                                        <li class="flex items-center gap-2">
+                                            <button onclick={show} title="Show message" class="grow text-left truncate hover:underline">
+                                                <span class="font-semibold">{m.from.clone()}</span>{": "}{preview(&m.message)}
+                                            </button>
+                                            <button onclick={unpin} title="Unpin" aria-label="Unpin" class={classes!("px-1", theme.muted())}>{"✕"}</button>
+                                        </li>
+                                    }
+                                }) }
+                            </ul>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
             </div>
         }
     }
+
+    /// Reply, copy and react for any message; edit and delete for our own.
+    fn render_message_menu(&self, ctx: &Context<Self>) -> Html {
+        let Some((id, x, y)) = &self.message_menu else {
+            return html! {};
+        };
+        let Some(message) = self.messages.iter().find(|m| m.id.as_ref() == Some(id)) else {
+            return html! {};
+        };
+        let action = |label: &str, msg: fn(String) -> Msg| {
+            let id = id.clone();
+            MenuItem::new(label, ctx.link().callback(move |_| msg(id.clone())))
+        };
+        let mut items = vec![
+            action("↩ Reply", Msg::ReplyTo),
+            action("⧉ Copy text", Msg::CopyMessage),
+            action("+ React", Msg::ToggleReactionPicker),
+        ];
+        // Pins belong to the room, so they aren't offered in direct messages.
+        if self.dm_peer.is_none() {
+            items.push(action(
+                if self.pinned.contains(id) {
+                    "📌 Unpin"
+                } else {
+                    "📌 Pin"
+                },
+                Msg::TogglePin,
+            ));
+        }
+        if message.from == self.username {
+            items.push(action("✎ Edit", Msg::StartEdit));
+            items.push(action("🗑 Delete", Msg::DeleteMessage).danger());
+        }
+        let on_close = ctx.link().callback(|_| Msg::CloseMessageMenu);
+        html! { <ContextMenu key={id.clone()} x={*x} y={*y} {items} {on_close} /> }
+    }
+
+    /// Runs message text through emoji, markdown, link, mention and search highlighting.
+    fn render_text(&self, message: &str) -> Html {
+        let text = emoji::replace_shortcodes(message);
+        let text = if self.settings.mask_profanity {
+            filter::mask_profanity(&text)
+        } else {
+            text
+        };
+        let highlighted = |s: &str| highlight(s, &self.search_query);
+        let mentioned = |s: &str| self.render_mentions(s, &highlighted);
+        let linked = |s: &str| linkify(s, &mentioned);
+        if self.settings.markdown {
+            markdown::render(&text, &linked)
+        } else {
+            linked(&text)
+        }
+    }
+
+    /// The avatar image, or generated initials once it has failed to load.
+    fn render_avatar(
+        &self,
+        ctx: &Context<Self>,
+        url: &str,
+        name: &str,
+        color: &str,
+        size: &str,
+    ) -> Html {
+        if self.broken_avatars.contains(url) {
+            return initials_avatar(name, color);
+        }
+        let failed = url.to_string();
+        let onerror = ctx
+            .link()
+            .callback(move |_: Event| Msg::AvatarFailed(failed.clone()));
+        html! { <img class={classes!(size.to_string(), "rounded-full")} src={url.to_string()} {onerror} alt={name.to_string()}/> }
+    }
+
+    /// A lazily loaded image behind a fixed-size skeleton until it arrives;
+    /// images that fail to load fall back to a plain link.
+    fn render_image(&self, ctx: &Context<Self>, url: &str) -> Html {
+        if self.broken_images.contains(url) {
+            return html! {
+                <div class="mt-3">
+                    <span class="mr-1">{"🖼 Image unavailable:"}</span>
+                    <a href={url.to_string()} target="_blank" rel="noopener noreferrer" class="text-blue-600 underline break-all">{url}</a>
+                </div>
+            };
+        }
+
+        let loaded = self.loaded_images.contains(url);
+        let (loaded_url, failed_url) = (url.to_string(), url.to_string());
+        let onload = ctx
+            .link()
+            .callback(move |_: Event| Msg::ImageLoaded(loaded_url.clone()));
+        let onerror = ctx
+            .link()
+            .callback(move |_: Event| Msg::ImageFailed(failed_url.clone()));
+        html! {
+            <div class={classes!("relative", "mt-3", (!loaded).then_some("w-48 h-32"))}>
+                { if loaded { html! {} } else { html! { <div class="absolute inset-0 rounded bg-gray-200 animate-pulse"></div> } } }
+                <img loading="lazy" src={url.to_string()} {onload} {onerror} alt="image"
+                    class={classes!(if loaded { "max-w-xs rounded" } else { "w-48 h-32 object-cover opacity-0" })} />
+            </div>
+        }
+    }
+
+    /// A placeholder bubble for an image being uploaded, or the reason it failed.
+    fn render_upload(&self, ctx: &Context<Self>, pending: &PendingUpload) -> Html {
+        let id = pending.id;
+        let status = match &pending.error {
+            None => html! { <span class={self.settings.theme.muted()}>{"Uploading…"}</span> },
+            Some(error) => html! {
+                <span class="flex gap-2 text-red-600">
+                    {error.to_string()}
+                    {
+                        if error.is_retryable() {
+                            html! { <button onclick={ctx.link().callback(move |_| Msg::RetryUpload(id))} class="font-semibold hover:underline">{"⟳ Retry"}</button> }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    <button onclick={ctx.link().callback(move |_| Msg::DiscardUpload(id))} class="font-semibold hover:underline">{"Discard"}</button>
+                </span>
+            },
+        };
+        html! {
+            <div key={format!("upload-{}", id)} class="flex flex-col items-end gap-1 mx-4 my-2 text-xs">
+                <div class="relative w-40 h-32 rounded-lg overflow-hidden border">
+                    <img src={pending.preview.clone()} alt={pending.file.name()} class="w-full h-full object-cover opacity-60" />
+                    {
+                        if pending.error.is_none() {
+                            html! {
+                                <div class="absolute inset-0 flex items-center justify-center">
+                                    <div class="w-6 h-6 rounded-full border-4 border-pink-300 border-t-pink-600 animate-spin"></div>
+                                </div>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                </div>
+                {status}
+            </div>
+        }
+    }
+
+    fn render_gif_picker(&self, ctx: &Context<Self>) -> Html {
+        let theme = self.settings.theme;
+        let body = if !giphy::is_configured() {
+            html! { <p class={classes!("text-sm", theme.muted())}>{GiphyError::NotConfigured.to_string()}</p> }
+        } else if self.gif_loading {
+            html! { <p class={classes!("text-sm", theme.muted())}>{"Searching…"}</p> }
+        } else {
+            match &self.gif_results {
+                None => html! {},
+                Some(Err(error)) => {
+                    html! { <p class="text-sm text-red-500">{error.to_string()}</p> }
+                }
+                Some(Ok(gifs)) if gifs.is_empty() => {
+                    html! { <p class={classes!("text-sm", theme.muted())}>{"No GIFs found"}</p> }
+                }
+                Some(Ok(gifs)) => html! {
+                    <div class="grid grid-cols-3 gap-1">
+                        { for gifs.iter().map(|gif| {
+                            let url = gif.url.clone();
+                            let onclick = ctx.link().callback(move |_| Msg::SendGif(url.clone()));
+                            html! {
+                                <button {onclick} title={gif.title.clone()} class="rounded overflow-hidden hover:ring-2 hover:ring-pink-400">
+                                    <img class="w-full h-20 object-cover" src={gif.preview_url.clone()} alt={gif.title.clone()} />
+                                </button>
+                            }
+                        }) }
+                    </div>
+                },
+            }
+        };
+        let onkeydown = ctx
+            .link()
+            .batch_callback(|e: KeyboardEvent| match e.key().as_str() {
+                "Enter" => {
+                    e.prevent_default();
+                    Some(Msg::SearchGifs)
+                }
+                "Escape" => Some(Msg::ToggleGifPicker),
+                _ => None,
+            });
+
+        html! {
+            <div class={classes!("absolute", "bottom-12", "left-0", "z-10", "w-80", "max-h-96", "overflow-y-auto", "p-2", "rounded-lg", "border", "shadow-lg", theme.input())}>
+                <input ref={self.gif_input.clone()} {onkeydown} type="search" placeholder="Search Giphy…" disabled={!giphy::is_configured()}
+                    class={classes!("w-full", "mb-2", "text-sm", "border", "rounded-full", "px-3", "py-1", theme.input())} />
+                {body}
+            </div>
+        }
+    }
+
+    /// Users matching the `@name` being typed, ourselves excluded.
+    fn mention_suggestions(&self) -> Vec<&str> {
+        let Some((_, partial)) = &self.mention_token else {
+            return Vec::new();
+        };
+        let partial = partial.to_lowercase();
+        self.users
+            .iter()
+            .map(|u| u.name.as_str())
+            .filter(|name| *name != self.username && name.to_lowercase().starts_with(&partial))
+            .take(MENTION_SUGGESTION_LIMIT)
+            .collect()
+    }
+
+    /// Turns `@name` for known users into chips, handing the rest to `text`.
+    fn render_mentions(&self, message: &str, text: &dyn Fn(&str) -> Html) -> Html {
+        let mut parts = Vec::new();
+        let mut pos = 0;
+        for (start, end) in mention_spans(message) {
+            let name = &message[start + 1..end];
+            if !self.users.iter().any(|u| u.name == name) {
+                continue;
+            }
+            if start > pos {
+                parts.push(text(&message[pos..start]));
+            }
+            let chip = if name == self.username {
+                "bg-amber-200 text-amber-900"
+            } else {
+                "bg-blue-100 text-blue-700"
+            };
+            parts.push(html! { <span class={classes!("px-1", "rounded", "font-semibold", chip)}>{&message[start..end]}</span> });
+            pos = end;
+        }
+        if pos < message.len() {
+            parts.push(text(&message[pos..]));
+        }
+        parts.into_iter().collect::<Html>()
+    }
+
+    /// The range of `len` rows worth materializing for the current scroll position.
+    fn render_window(&self, len: usize) -> (usize, usize) {
+        if len <= VIRTUALIZE_THRESHOLD {
+            return (0, len);
+        }
+        let row_px = self.settings.density.estimated_row_px();
+        let first = (self.scroll_top / row_px).max(0) as usize;
+        let last = ((self.scroll_top + self.viewport_height) / row_px).max(0) as usize + 1;
+        let start = first.saturating_sub(OVERSCAN_ROWS).min(len);
+        let end = (last + OVERSCAN_ROWS).min(len).max(start);
+        (start, end)
+    }
+
+    /// Messages in the open conversation that aren't muted or filtered out by search.
+    fn visible_messages(&self) -> Vec<&MessageData> {
+        self.messages
+            .iter()
+            .filter(|m| !self.muted_users.contains(&m.from) && self.matches_search(m))
+            .collect()
+    }
+
+    /// Renders only the rows inside the scroll window, with spacers standing in
+    /// for the rest so the scrollbar keeps its size.
+    fn render_messages(&self, ctx: &Context<Self>) -> Html {
+        let hidden = self
+            .messages
+            .iter()
+            .filter(|m| self.muted_users.contains(&m.from))
+            .count();
+        let muted_notice = if hidden > 0 {
+            let unmute = ctx.link().callback(|_| Msg::UnmuteAll);
+            html! {
+                <div class={classes!("flex", "justify-center", "gap-2", "text-xs", "py-1", self.settings.theme.muted())}>
+                    {format!("{} message{} from muted users hidden", hidden, if hidden == 1 { "" } else { "s" })}
+                    <button onclick={unmute} class="underline hover:no-underline">{"Unmute all"}</button>
+                </div>
+            }
+        } else {
+            html! {}
+        };
+        let visible = self.visible_messages();
+        if visible.is_empty() {
+            let text = if self.messages.is_empty() {
+                "No messages yet — say hi! 👋"
+            } else {
+                "No messages match your search"
+            };
+            return html! {
+                <>
+                    {muted_notice}
+                    <div class={classes!("h-full", "flex", "items-center", "justify-center", "text-sm", self.settings.theme.muted())}>{text}</div>
+                </>
+            };
+        }
+        let (start, end) = self.render_window(visible.len());
+        let first_new = self.read_until.and_then(|read_until| {
+            visible.iter().position(|m| {
+                m.from != self.username && m.timestamp.is_some_and(|ts| ts > read_until)
+            })
+        });
+        let new_divider = |count: usize| {
+            html! {
+                <div id={NEW_DIVIDER_ID} class="flex items-center gap-3 my-3 text-xs font-semibold text-red-500">
+                    <div class="grow border-t border-red-400"></div>
+                    {format!("{} new message{}", count, if count == 1 { "" } else { "s" })}
+                    <div class="grow border-t border-red-400"></div>
+                </div>
+            }
+        };
+        let row_px = self.settings.density.estimated_row_px();
+        let spacer = |rows: usize| {
+            if rows == 0 {
+                html! {}
+            } else {
+                html! { <div style={format!("height:{}px", rows as i32 * row_px)}></div> }
+            }
+        };
+
+        html! {
+            <>
+                {muted_notice}
+                {spacer(start)}
+                {
+                    for visible[start..end].iter().enumerate().map(|(i, m)| {
+                        // Compare with the previous timestamped message, even if it's outside the window.
+                        let previous_day = visible[..start + i].iter().rev().find_map(|p| p.timestamp).map(local_day);
+                        let new_day = m.timestamp.is_some_and(|ts| previous_day != Some(local_day(ts)));
+                        let grouped = !new_day
+                            && first_new != Some(start + i)
+                            && (start + i).checked_sub(1).is_some_and(|p| continues_group(visible[p], m));
+                        let separator = match m.timestamp {
+                            Some(ts) if new_day => html! {
+                                <div class={classes!("flex", "items-center", "gap-3", "my-3", "text-xs", self.settings.theme.muted())}>
+                                    <div class={classes!("grow", "border-t", self.settings.theme.divider())}></div>
+                                    {format_day_label(ts)}
+                                    <div class={classes!("grow", "border-t", self.settings.theme.divider())}></div>
+                                </div>
+                            },
+                            _ => html! {},
+                        };
+                        let divider = match first_new {
+                            Some(first) if first == start + i => {
+                                new_divider(visible[first..].iter().filter(|m| m.from != self.username).count())
+                            }
+                            _ => html! {},
+                        };
+                        // Keyed so per-message state (e.g. "Show more") follows its message.
+                        let key = m.id.clone().unwrap_or_else(|| format!("#{}", start + i));
+                        html! {
+                            <key={key}>
+                                {divider}
+                                {separator}
+                                {self.render_message(ctx, m, m.from == self.username, grouped)}
+                            </>
+                        }
+                    })
+                }
+                {spacer(visible.len() - end)}
+            </>
+        }
+    }
+
+    fn matches_search(&self, m: &MessageData) -> bool {
+        self.search_query.is_empty()
+            || (m.kind != MessageKind::System
+                && (m.message.to_lowercase().contains(&self.search_query)
+                    || m.from.to_lowercase().contains(&self.search_query)))
+    }
+
+    fn typing_text(&self) -> Option<String> {
+        // Typing notices are per room.
+        if self.dm_peer.is_some() {
+            return None;
+        }
+        let mut names: Vec<String> = self.typing.keys().cloned().collect();
+        // Sorted so the label doesn't reshuffle as notices come and go.
+        names.sort();
+        (!names.is_empty()).then(|| typing_label(&names))
+    }
+}
+
+/// "Alice is typing…", "Alice, Bob and Carol are typing…", or past
+/// `TYPING_NAMES_SHOWN` names, "Alice, Bob and 3 others are typing…".
+/// Empty for nobody.
+fn typing_label(names: &[String]) -> String {
+    let (shown, others) = if names.len() > TYPING_NAMES_SHOWN {
+        (
+            &names[..TYPING_NAMES_SHOWN - 1],
+            names.len() - (TYPING_NAMES_SHOWN - 1),
+        )
+    } else {
+        (names, 0)
+    };
+    match (shown, others) {
+        ([], _) => String::new(),
+        ([one], 0) => format!("{} is typing…", one),
+        ([rest @ .., last], 0) => format!("{} and {} are typing…", rest.join(", "), last),
+        (shown, others) => format!(
+            "{} and {} other{} are typing…",
+            shown.join(", "),
+            others,
+            if others == 1 { "" } else { "s" }
+        ),
+    }
+}
+
+/// A reasonably unique id for messages we send.
+fn client_id() -> String {
+    format!(
+        "{:x}-{:x}",
+        js_sys::Date::now() as u64,
+        (js_sys::Math::random() * u32::MAX as f64) as u32
+    )
+}
+
+/// Sidebar section for `name`: its upper-cased first letter, or `#` for
+/// anything that isn't a letter.
+fn section_letter(name: &str) -> String {
+    match name.chars().next() {
+        Some(c) if c.is_alphabetic() => c.to_uppercase().collect(),
+        _ => "#".to_string(),
+    }
+}
+
+const IMAGE_EXTENSIONS: [&str; 5] = [".png", ".jpg", ".jpeg", ".webp", ".gif"];
+
+/// Only plain `http(s)://` URLs may end up in an `src`; `javascript:`, `data:`
+/// and anything with whitespace or control characters are rejected.
+fn is_safe_url(s: &str) -> bool {
+    let (scheme, rest) = match s.split_once(':') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let scheme = scheme.to_ascii_lowercase();
+    (scheme == "http" || scheme == "https")
+        && rest.starts_with("//")
+        && rest.len() > 2
+        && !s
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == '"' || c == '<' || c == '>')
+}
+
+/// Whether a message is an http(s) link to an image we can render inline.
+fn is_image_url(s: &str) -> bool {
+    if !is_safe_url(s) {
+        return false;
+    }
+    let path = s
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    IMAGE_EXTENSIONS.iter().any(|ext| path.ends_with(ext))
+}
+
+/// Byte ranges of `@name` tokens, `@` included. A token must start the text or
+/// follow a non-word character, so `mail@example.com` isn't a mention.
+fn mention_spans(message: &str) -> Vec<(usize, usize)> {
+    let is_name_char = |c: char| c.is_alphanumeric() || c == '_';
+    let mut spans = Vec::new();
+    for (start, _) in message.match_indices('@') {
+        if message[..start]
+            .chars()
+            .next_back()
+            .is_some_and(is_name_char)
+        {
+            continue;
+        }
+        let name = &message[start + 1..];
+        let len = name.find(|c: char| !is_name_char(c)).unwrap_or(name.len());
+        if len > 0 {
+            spans.push((start, start + 1 + len));
+        }
+    }
+    spans
+}
+
+/// The `@name` token the caret (a UTF-16 offset into `value`) is in, if any.
+fn mention_token_at(value: &str, caret: u32) -> Option<(u32, String)> {
+    let utf16: Vec<u16> = value.encode_utf16().collect();
+    let before = String::from_utf16_lossy(utf16.get(..caret as usize)?);
+    let at = before.rfind('@')?;
+    let partial = &before[at + 1..];
+    let is_name_char = |c: char| c.is_alphanumeric() || c == '_';
+    if !partial.chars().all(is_name_char)
+        || before[..at].chars().next_back().is_some_and(is_name_char)
+    {
+        return None;
+    }
+    let start = before[..at].encode_utf16().count() as u32;
+    Some((start, partial.to_string()))
+}
+
+/// Usernames of `users` mentioned in `message`, without duplicates.
+fn extract_mentions(message: &str, users: &[UserProfile]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for (start, end) in mention_spans(message) {
+        let name = &message[start + 1..end];
+        if users.iter().any(|u| u.name == name) && !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// Splits plain text into text and `<a>` segments for every http(s) URL in it,
+/// handing non-link runs to `text`. Trailing punctuation such as the full stop
+/// in `see http://x.com.` stays text.
+fn linkify(message: &str, text: &dyn Fn(&str) -> Html) -> Html {
+    let mut parts = Vec::new();
+    let mut rest = message;
+
+    while let Some(start) = ["http://", "https://"]
+        .iter()
+        .filter_map(|p| rest.find(p))
+        .min()
+    {
+        let candidate = &rest[start..];
+        let end = candidate
+            .find(char::is_whitespace)
+            .unwrap_or(candidate.len());
+        let url =
+            candidate[..end].trim_end_matches(['.', ',', '!', '?', ';', ':', ')', ']', '\'', '"']);
+
+        if !is_safe_url(url) {
+            parts.push(text(&rest[..start + end]));
+            rest = &rest[start + end..];
+            continue;
+        }
+        if start > 0 {
+            parts.push(text(&rest[..start]));
+        }
+        parts.push(html! {
+            <a href={url.to_string()} target="_blank" rel="noopener noreferrer" class="text-blue-600 underline break-all">{url}</a>
+        });
+        rest = &rest[start + url.len()..];
+    }
+    if !rest.is_empty() {
+        parts.push(text(rest));
+    }
+
+    parts.into_iter().collect::<Html>()
+}
+
+/// Wraps case-insensitive occurrences of `query` (already lower-cased) in `<mark>`.
+fn highlight(text: &str, query: &str) -> Html {
+    let lower = text.to_lowercase();
+    // Lower-casing can change byte lengths for some scripts; skip highlighting then.
+    if query.is_empty() || lower.len() != text.len() {
+        return html! { {text} };
+    }
+
+    let mut parts = Vec::new();
+    let mut pos = 0;
+    for (start, _) in lower.match_indices(query) {
+        if start < pos {
+            continue;
+        }
+        if start > pos {
+            parts.push(html! { {&text[pos..start]} });
+        }
+        let end = start + query.len();
+        parts.push(html! { <mark class="bg-yellow-200 rounded-sm">{&text[start..end]}</mark> });
+        pos = end;
+    }
+    if pos < text.len() {
+        parts.push(html! { {&text[pos..]} });
+    }
+    parts.into_iter().collect::<Html>()
+}
+
+/// "just now", "5m", "3h" or "2d" for how long ago `ts` was.
+fn format_relative(ts: f64, now: f64) -> String {
+    let seconds = ((now - ts) / 1_000.0).max(0.0) as u64;
+    match seconds {
+        0..=59 => "just now".to_string(),
+        60..=3_599 => format!("{}m", seconds / 60),
+        3_600..=86_399 => format!("{}h", seconds / 3_600),
+        _ => format!("{}d", seconds / 86_400),
+    }
+}
+
+/// A millisecond timestamp as a local time of day, in the browser's locale
+/// and time zone (so DST is applied). For example, in `en-US`:
+///
+/// | time  | `hour12 = true` | `hour12 = false` |
+/// |-------|-----------------|------------------|
+/// | noon  | `12:00 PM`      | `12:00`          |
+/// | 00:00 | `12:00 AM`      | `00:00`          |
+/// | 15:30 | `3:30 PM`       | `15:30`          |
+fn format_time(ts: f64, hour12: bool) -> String {
+    js_sys::Date::new(&JsValue::from_f64(ts))
+        .to_locale_string("default", &time_options(hour12))
+        .into()
+}
+
+/// Full local date and time, used for the timestamp tooltip.
+fn format_full_date(ts: f64, hour12: bool) -> String {
+    let options = time_options(hour12);
+    for (key, value) in [("year", "numeric"), ("month", "short"), ("day", "numeric")] {
+        let _ = js_sys::Reflect::set(&options, &key.into(), &value.into());
+    }
+    js_sys::Date::new(&JsValue::from_f64(ts))
+        .to_locale_string("default", &options)
+        .into()
+}
+
+/// `Intl` options for hours and minutes on a 12- or 24-hour clock.
+fn time_options(hour12: bool) -> js_sys::Object {
+    let options = js_sys::Object::new();
+    let _ = js_sys::Reflect::set(&options, &"hour".into(), &"numeric".into());
+    let _ = js_sys::Reflect::set(&options, &"minute".into(), &"2-digit".into());
+    if hour12 {
+        let _ = js_sys::Reflect::set(&options, &"hour12".into(), &true.into());
+    } else {
+        // `hour12: false` shows midnight as "24:00" in some browsers.
+        let _ = js_sys::Reflect::set(&options, &"hourCycle".into(), &"h23".into());
+    }
+    options
+}
+
+/// An inline SVG avatar with the first letter of `name`. It needs no network,
+/// so it can't fail the way the image it replaces did.
+fn initials_avatar(name: &str, color: &str) -> Html {
+    let initial = name
+        .chars()
+        .next()
+        .map(|c| c.to_uppercase().to_string())
+        .unwrap_or_else(|| "?".to_string());
+    html! {
+        <svg class="w-full h-full rounded-full" viewBox="0 0 40 40" role="img" aria-label={name.to_string()}>
+            <rect width="40" height="40" fill={color.to_string()} />
+            <text x="20" y="26" text-anchor="middle" font-size="18" font-weight="600" fill="#374151">{initial}</text>
+        </svg>
+    }
+}
+
+/// Appends `message`, then drops the oldest ones beyond `cap` and returns them.
+fn push_capped(
+    messages: &mut VecDeque<MessageData>,
+    message: MessageData,
+    cap: usize,
+) -> Vec<MessageData> {
+    messages.push_back(message);
+    let excess = messages.len().saturating_sub(cap);
+    messages.drain(..excess).collect()
+}
+
+/// Removes and returns the message with `id`, if `from` sent it.
+fn remove_by_id(messages: &mut VecDeque<MessageData>, id: &str, from: &str) -> Option<MessageData> {
+    let position = messages
+        .iter()
+        .position(|m| m.id.as_deref() == Some(id) && m.from == from)?;
+    messages.remove(position)
+}
+
+/// Replaces the text of the message with `id`, marking it edited.
+fn edit_by_id(messages: &mut VecDeque<MessageData>, id: &str, text: String) -> bool {
+    match messages.iter_mut().find(|m| m.id.as_deref() == Some(id)) {
+        Some(m) => {
+            m.message = text;
+            m.edited = true;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Smoothly scrolls `element` to the middle of the message list.
+fn reveal(element: &Element) {
+    let mut options = ScrollIntoViewOptions::new();
+    options
+        .behavior(ScrollBehavior::Smooth)
+        .block(ScrollLogicalPosition::Center);
+    element.scroll_into_view_with_scroll_into_view_options(&options);
+}
+
+/// Uploads `file` in the background and reports back with `UploadFinished`.
+fn start_upload(ctx: &Context<Chat>, id: u32, file: File) {
+    ctx.link().send_future(async move {
+        Msg::UploadFinished {
+            id,
+            result: upload::upload(&file).await,
+        }
+    });
+}
+
+/// The files carried by a drop or paste, if any.
+fn files_in(data: Option<DataTransfer>) -> Vec<File> {
+    let Some(files) = data.and_then(|d| d.files()) else {
+        return Vec::new();
+    };
+    (0..files.length()).filter_map(|i| files.get(i)).collect()
+}
+
+/// DOM id of a message's text, selected when copying to the clipboard fails.
+fn message_text_element_id(id: &str) -> String {
+    format!("msg-text-{}", id)
+}
+
+/// Whether `target` accepts typed text, so shortcuts should leave the key alone.
+fn is_editable(target: Option<web_sys::EventTarget>) -> bool {
+    let Some(element) = target.and_then(|t| t.dyn_into::<Element>().ok()) else {
+        return false;
+    };
+    matches!(element.tag_name().as_str(), "INPUT" | "TEXTAREA" | "SELECT")
+        || element
+            .get_attribute("contenteditable")
+            .is_some_and(|v| v != "false")
+}
+
+/// The first line of a message, shortened for quotes and banners.
+fn preview(text: &str) -> String {
+    const PREVIEW_CHARS: usize = 80;
+    let line = text.lines().next().unwrap_or_default();
+    if line.chars().count() > PREVIEW_CHARS {
+        format!("{}…", line.chars().take(PREVIEW_CHARS).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+/// Whether `m` continues a burst started by `previous`: same sender, both user
+/// messages, and sent within `GROUP_GAP_MS` of each other.
+fn continues_group(previous: &MessageData, m: &MessageData) -> bool {
+    previous.kind == MessageKind::User
+        && m.kind == MessageKind::User
+        && previous.from == m.from
+        && match (previous.timestamp, m.timestamp) {
+            (Some(a), Some(b)) => b - a <= GROUP_GAP_MS,
+            _ => false,
+        }
+}
+
+/// Local calendar day of a timestamp, as (year, month, day).
+fn local_day(ts: f64) -> (u32, u32, u32) {
+    let date = js_sys::Date::new(&JsValue::from_f64(ts));
+    (date.get_full_year(), date.get_month(), date.get_date())
+}
+
+/// "Today", "Yesterday", or the full date in the user's locale.
+fn format_day_label(ts: f64) -> String {
+    let day = local_day(ts);
+    let now = js_sys::Date::now();
+    if day == local_day(now) {
+        return "Today".to_string();
+    }
+    // Step back from today's local noon so DST changes can't skip a day.
+    let today = js_sys::Date::new(&JsValue::from_f64(now));
+    today.set_hours(12);
+    if day == local_day(today.get_time() - 86_400_000.0) {
+        return "Yesterday".to_string();
+    }
+
+    let options = js_sys::Object::new();
+    for (key, value) in [
+        ("weekday", "long"),
+        ("year", "numeric"),
+        ("month", "long"),
+        ("day", "numeric"),
+    ] {
+        let _ = js_sys::Reflect::set(&options, &key.into(), &value.into());
+    }
+    js_sys::Date::new(&JsValue::from_f64(ts))
+        .to_locale_date_string("default", &options)
+        .into()
+}
+
+impl Component for Chat {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let user = match ctx.link().context::<User>(Callback::noop()) {
+            Some((user, _)) => user,
+            // `ChatRoute` normally catches this; send them to log in rather than panic.
+            None => {
+                if let Some(history) = ctx.link().history() {
+                    history.push(Route::Login);
+                }
+                Rc::new(UserInner::signed_out())
+            }
+        };
+        let username = user.username.borrow().clone();
+        // Without a username we're on our way to the login page; don't open a
+        // session the server would show as a nameless user.
+        let wss = if username.is_empty() {
+            WebsocketService::offline()
+        } else {
+            let wss = WebsocketService::new();
+            let message = WebSocketMessage {
+                avatar_style: Some(user.avatar_style.borrow().clone()),
+                ..WebSocketMessage::new(MsgTypes::Register, Some(username.to_string()))
+            };
+            wss.register(serde_json::to_string(&message).unwrap());
+            wss
+        };
+
+        let settings = Settings::load();
+        settings.font_size.apply();
+
+        Self {
+            users: vec![],
+            messages: VecDeque::new(),
+            history_request: None,
+            history_exhausted: false,
+            history_anchor: None,
+            chat_input: NodeRef::default(),
+            wss,
+            reconnect_attempt: None,
+            user,
+            username,
+            typing: HashMap::new(),
+            arrived: HashMap::new(),
+            announcements: VecDeque::new(),
+            next_announcement_id: 0,
+            current_room: DEFAULT_ROOM.to_string(),
+            settings,
+            export_menu_open: false,
+            settings_open: false,
+            sidebar_open: false,
+            message_menu: None,
+            profile_card: None,
+            profile_hide: None,
+            pinned: Vec::new(),
+            pinned_panel_open: true,
+            muted_users: storage::get::<MutedUsers>().0,
+            sound_unlocked: false,
+            notification_sound: None,
+            original_title: gloo::utils::document().title(),
+            hidden_unread: 0,
+            read_until: None,
+            new_divider_timeout: None,
+            search_open: false,
+            search_input: NodeRef::default(),
+            search_query: String::new(),
+            search_debounce: None,
+            _tick: {
+                let link = ctx.link().clone();
+                Interval::new(TICK_INTERVAL_MS, move || link.send_message(Msg::Tick))
+            },
+            broken_avatars: HashSet::new(),
+            loaded_images: HashSet::new(),
+            broken_images: HashSet::new(),
+            gif_picker_open: false,
+            gif_input: NodeRef::default(),
+            gif_search_seq: 0,
+            gif_results: None,
+            gif_loading: false,
+            uploads: Vec::new(),
+            next_upload_id: 0,
+            drag_over: false,
+            emoji_picker: NodeRef::default(),
+            emoji_picker_listeners: Vec::new(),
+            _visibility_listener: {
+                let link = ctx.link().clone();
+                EventListener::new(&gloo::utils::document(), "visibilitychange", move |_| {
+                    link.send_message(Msg::VisibilityChanged)
+                })
+            },
+            idle_timer: Self::idle_timer(ctx),
+            away: false,
+            away_users: HashSet::new(),
+            _activity_listeners: {
+                let last_activity = Rc::new(Cell::new(js_sys::Date::now()));
+                ACTIVITY_EVENTS
+                    .iter()
+                    .map(|event| {
+                        let link = ctx.link().clone();
+                        let last_activity = last_activity.clone();
+                        EventListener::new(&gloo::utils::document(), *event, move |_| {
+                            let now = js_sys::Date::now();
+                            if now - last_activity.get() >= ACTIVITY_DEBOUNCE_MS {
+                                last_activity.set(now);
+                                link.send_message(Msg::Activity);
+                            }
+                        })
+                    })
+                    .collect()
+            },
+            _shortcut_listener: {
+                let link = ctx.link().clone();
+                EventListener::new(&gloo::utils::document(), "keydown", move |e| {
+                    let Some(key) = e.dyn_ref::<KeyboardEvent>() else {
+                        return;
+                    };
+                    if key.key() == "/"
+                        && !key.ctrl_key()
+                        && !key.meta_key()
+                        && !key.alt_key()
+                        && !is_editable(e.target())
+                    {
+                        e.prevent_default();
+                        link.send_message(Msg::FocusInput);
+                    }
+                })
+            },
+            _unload_listener: {
+                let link = ctx.link().clone();
+                EventListener::new(&gloo::utils::window(), "beforeunload", move |_| {
+                    link.send_message(Msg::PageUnload)
+                })
+            },
+            offline: !gloo::utils::window().navigator().on_line(),
+            _network_listeners: ["online", "offline"]
+                .iter()
+                .map(|event| {
+                    let link = ctx.link().clone();
+                    EventListener::new(&gloo::utils::window(), *event, move |_| {
+                        link.send_message(Msg::NetworkChanged)
+                    })
+                })
+                .collect(),
+            last_typing_sent: 0.0,
+            message_list: NodeRef::default(),
+            scroll_top: 0,
+            viewport_height: DEFAULT_VIEWPORT_PX,
+            stick_to_bottom: true,
+            scroll_pending: false,
+            unread: 0,
+            reactions: HashMap::new(),
+            reaction_picker: None,
+            send_limiter: RateLimiter::new(SEND_LIMIT, SEND_WINDOW_MS),
+            toasts: VecDeque::new(),
+            next_toast_id: 0,
+            copied: None,
+            copied_reset: None,
+            replying_to: None,
+            reveal_pending: None,
+            highlighted: None,
+            highlight_reset: None,
+            jump_pending: None,
+            focused_message: None,
+            focus_pending: None,
+            menu_from_keyboard: false,
+            dm_peer: None,
+            stashed: HashMap::new(),
+            dm_unread: HashMap::new(),
+            editing: None,
+            mention_token: None,
+            mention_selected: 0,
+            draft_len: 0,
+            ready: false,
+            _producer: EventBus::bridge(ctx.link().callback(Msg::HandleMsg)),
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::HandleMsg(event) => match event {
+                BusEvent::Users(mut entries) => {
+                    // We always know our own style, even if the server doesn't relay it.
+                    for entry in entries.iter_mut().filter(|e| e.name == self.username) {
+                        entry.avatar_style = Some(self.user.avatar_style.borrow().clone());
+                    }
+                    let users = self.parse_users(entries);
+                    if self.ready && users == self.users {
+                        return false;
+                    }
+                    if self.ready {
+                        let joined = users
+                            .iter()
+                            .filter(|u| !self.users.iter().any(|o| o.name == u.name))
+                            .map(|u| format!("{} joined the chat", u.name));
+                        let left = self
+                            .users
+                            .iter()
+                            .filter(|o| !users.iter().any(|u| u.name == o.name))
+                            .map(|u| format!("{} left the chat", u.name));
+                        let notices: Vec<String> = joined.chain(left).collect();
+                        for notice in notices {
+                            self.push_message(MessageData::system(notice));
+                        }
+                        self.scroll_pending = self.stick_to_bottom;
+                    }
+                    self.ready = true;
+                    self.away_users
+                        .retain(|name| users.iter().any(|u| u.name == *name));
+                    self.users = users;
+                    true
+                }
+                BusEvent::Message(message_data) => {
+                    if message_data.room() != self.current_room {
+                        return false;
+                    }
+                    self.typing.remove(&message_data.from);
+                    if self.dm_peer.is_some() {
+                        if message_data.from != self.username {
+                            self.stash_message(None, message_data);
+                        }
+                        return false;
+                    }
+                    if message_data.from == self.username {
+                        if self.confirm_pending(&message_data) {
+                            return true;
+                        }
+                    } else if !self.muted_users.contains(&message_data.from) {
+                        let hidden = notifications::page_hidden();
+                        let mentioned = extract_mentions(&message_data.message, &self.users)
+                            .contains(&self.username);
+                        if hidden || mentioned {
+                            let title = if mentioned {
+                                format!("{} mentioned you", message_data.from)
+                            } else {
+                                message_data.from.clone()
+                            };
+                            notifications::show(&title, &message_data.message);
+                        }
+                        if hidden {
+                            self.hidden_unread += 1;
+                            self.update_title();
+                        }
+                        if hidden || mentioned || !self.stick_to_bottom {
+                            self.play_notification_sound();
+                        }
+                    }
+                    let muted = self.muted_users.contains(&message_data.from);
+                    self.push_message(message_data);
+                    self.scroll_pending = self.stick_to_bottom;
+                    if !self.stick_to_bottom && !muted {
+                        self.unread += 1;
+                    }
+                    true
+                }
+                BusEvent::Edit { id, message, room } => {
+                    room == self.current_room && self.apply_edit(&id, message)
+                }
+                BusEvent::Delete { id, from, room } => {
+                    room == self.current_room && self.remove_message(&id, &from)
+                }
+                BusEvent::Delivered(id) => self.mark_delivered(&id),
+                BusEvent::Reaction {
+                    id,
+                    emoji,
+                    from,
+                    room,
+                } => {
+                    // Our own reactions were applied when sent; an echo would undo them.
+                    if room != self.current_room || from == self.username {
+                        return false;
+                    }
+                    self.toggle_reaction(id, emoji, from);
+                    true
+                }
+                BusEvent::Left(username) => {
+                    // Our own leave only echoes back while we're on our way out.
+                    if username == self.username {
+                        return false;
+                    }
+                    let Some(index) = self.users.iter().position(|u| u.name == username) else {
+                        return false;
+                    };
+                    self.users.remove(index);
+                    self.away_users.remove(&username);
+                    self.typing.remove(&username);
+                    self.push_message(MessageData::system(format!("{} left the chat", username)));
+                    self.scroll_pending = self.stick_to_bottom;
+                    true
+                }
+                BusEvent::Presence { username, away } => {
+                    // Our own status is tracked locally.
+                    if username == self.username {
+                        return false;
+                    }
+                    if away {
+                        self.away_users.insert(username)
+                    } else {
+                        self.away_users.remove(&username)
+                    }
+                }
+                BusEvent::Pin {
+                    id,
+                    pinned,
+                    from,
+                    room,
+                } => {
+                    // Our own pins were applied when sent.
+                    if room != self.current_room || from == self.username {
+                        return false;
+                    }
+                    self.set_pinned(id, pinned)
+                }
+                BusEvent::DirectMessage { to, message } => {
+                    let own = message.from == self.username;
+                    let peer = if own { to } else { message.from.clone() };
+                    let muted = self.muted_users.contains(&message.from);
+                    if self.dm_peer.as_ref() != Some(&peer) {
+                        if own {
+                            return false;
+                        }
+                        if !muted {
+                            *self.dm_unread.entry(peer.clone()).or_default() += 1;
+                            if notifications::page_hidden() {
+                                notifications::show(
+                                    &format!("{} (direct)", peer),
+                                    &message.message,
+                                );
+                            }
+                            self.play_notification_sound();
+                        }
+                        self.stash_message(Some(peer), message);
+                        return !muted;
+                    }
+                    if own && self.confirm_pending(&message) {
+                        return true;
+                    }
+                    self.push_message(message);
+                    self.scroll_pending = self.stick_to_bottom;
+                    if !self.stick_to_bottom && !muted {
+                        self.unread += 1;
+                    }
+                    true
+                }
+                BusEvent::Typing { username, room } => {
+                    if room != self.current_room || username == self.username {
+                        return false;
+                    }
+                    let link = ctx.link().clone();
+                    let expired = username.clone();
+                    let timeout = Timeout::new(TYPING_TIMEOUT_MS, move || {
+                        link.send_message(Msg::TypingExpired(expired))
+                    });
+                    self.typing.insert(username, timeout);
+                    true
+                }
+                BusEvent::Reconnecting(attempt) => {
+                    self.reconnect_attempt = Some(attempt);
+                    true
+                }
+                BusEvent::RegisterRejected(reason) => {
+                    self.wss.close();
+                    *self.user.login_error.borrow_mut() = Some(reason);
+                    if let Some(history) = ctx.link().history() {
+                        history.push(Route::Login);
+                    }
+                    false
+                }
+                BusEvent::History { room, messages } => {
+                    if room != self.current_room
+                        || self.dm_peer.is_some()
+                        || self.history_request.take().is_none()
+                    {
+                        return false;
+                    }
+                    self.prepend_history(messages);
+                    if let Some(id) = self.jump_pending.take() {
+                        if self.messages.iter().any(|m| m.id.as_ref() == Some(&id)) {
+                            ctx.link().send_message(Msg::ScrollToMessage(id));
+                        } else if self.request_history(ctx) {
+                            // Not in this batch; keep going back until it turns up or history runs out.
+                            self.jump_pending = Some(id);
+                        } else {
+                            self.show_toast(
+                                ctx,
+                                ToastKind::Warning,
+                                "Original message unavailable.".to_string(),
+                            );
+                        }
+                    }
+                    true
+                }
+                // Read back from the service when rendering.
+                BusEvent::Latency(_) => true,
+                BusEvent::ServerError(reason) => {
+                    self.show_toast(ctx, ToastKind::Error, reason);
+                    true
+                }
+                BusEvent::MalformedMessage => {
+                    self.wss.parse_failure_count() >= MALFORMED_WARNING_THRESHOLD
+                }
+                BusEvent::Status(state) => {
+                    // The service already tracks the state; this only triggers a re-render.
+                    if state == ConnectionState::Connected {
+                        self.reconnect_attempt = None;
+                    }
+                    true
+                }
+            },
+            Msg::SubmitMessage => {
+                notifications::request_permission();
+                self.sound_unlocked = true;
+                if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+                    let raw = input.value();
+                    if raw.chars().count() > MAX_MESSAGE_LEN {
+                        self.show_toast(
+                            ctx,
+                            ToastKind::Warning,
+                            format!(
+                                "Messages can be at most {} characters long.",
+                                MAX_MESSAGE_LEN
+                            ),
+                        );
+                        return true;
+                    }
+                    let text = raw.trim().to_string();
+                    if !text.is_empty() && !self.send_limiter.try_acquire(js_sys::Date::now()) {
+                        self.show_toast(
+                            ctx,
+                            ToastKind::Warning,
+                            "You're sending messages too quickly, slow down a little.".to_string(),
+                        );
+                        return true;
+                    }
+                    if let Some(id) = self.editing.take() {
+                        if !text.is_empty() {
+                            let edit = WebSocketMessage {
+                                id: Some(id.clone()),
+                                room: Some(self.current_room.clone()),
+                                ..WebSocketMessage::new(MsgTypes::Edit, Some(text.clone()))
+                            };
+                            let _ = self
+                                .wss
+                                .tx
+                                .clone()
+                                .try_send(serde_json::to_string(&edit).unwrap());
+                            self.apply_edit(&id, text);
+                        }
+                        self.clear_input();
+                        return true;
+                    }
+                    let (text, kind) = match commands::parse_command(&text) {
+                        None => match text.strip_prefix("//") {
+                            Some(rest) => (format!("/{}", rest), MessageKind::User),
+                            None => (text, MessageKind::User),
+                        },
+                        Some(Command::Me(action)) if !action.is_empty() => {
+                            (action, MessageKind::Action)
+                        }
+                        Some(Command::Shrug(rest)) if rest.is_empty() => {
+                            (commands::SHRUG.to_string(), MessageKind::User)
+                        }
+                        Some(Command::Shrug(rest)) => {
+                            (format!("{} {}", rest, commands::SHRUG), MessageKind::User)
+                        }
+                        Some(command) => {
+                            let hint = match command {
+                                Command::Unknown(name) => {
+                                    format!("Unknown command /{}. {}", name, Command::usage())
+                                }
+                                _ => Command::usage().to_string(),
+                            };
+                            self.push_message(MessageData::system(hint));
+                            self.scroll_pending = true;
+                            return true;
+                        }
+                    };
+                    if !text.is_empty() {
+                        self.clear_input();
+                        self.last_typing_sent = 0.0;
+                        self.send_message(text, kind);
+                        return true;
+                    }
+                }
+                false
+            }
+            Msg::InputChanged => {
+                self.sound_unlocked = true;
+                let now = js_sys::Date::now();
+                if now - self.last_typing_sent >= TYPING_DEBOUNCE_MS {
+                    self.last_typing_sent = now;
+                    let message = WebSocketMessage {
+                        room: Some(self.current_room.clone()),
+                        ..WebSocketMessage::new(MsgTypes::Typing, Some(self.username.clone()))
+                    };
+                    let _ = self
+                        .wss
+                        .tx
+                        .clone()
+                        .try_send(serde_json::to_string(&message).unwrap());
+                }
+                self.fit_input();
+                let input = self.chat_input.cast::<HtmlTextAreaElement>();
+                let value = input.as_ref().map(|i| i.value()).unwrap_or_default();
+                self.save_draft(&value);
+                let token = input
+                    .and_then(|i| mention_token_at(&value, i.selection_start().ok().flatten()?));
+                let mentions_changed = token != self.mention_token;
+                if mentions_changed {
+                    self.mention_token = token;
+                    self.mention_selected = 0;
+                }
+                let len = value.chars().count();
+                let changed = len != self.draft_len || mentions_changed;
+                self.draft_len = len;
+                changed
+            }
+            Msg::MoveMentionSelection(step) => {
+                let count = self.mention_suggestions().len() as i32;
+                if count == 0 {
+                    return false;
+                }
+                self.mention_selected =
+                    (self.mention_selected as i32 + step).rem_euclid(count) as usize;
+                true
+            }
+            Msg::CompleteMention(choice) => {
+                let suggestions = self.mention_suggestions();
+                let name = match suggestions.get(choice.unwrap_or(self.mention_selected)) {
+                    Some(name) => name.to_string(),
+                    None => return false,
+                };
+                let (Some((start, _)), Some(input)) = (
+                    self.mention_token.take(),
+                    self.chat_input.cast::<HtmlTextAreaElement>(),
+                ) else {
+                    return false;
+                };
+                let mut value: Vec<u16> = input.value().encode_utf16().collect();
+                let start = (start as usize).min(value.len());
+                let end = input
+                    .selection_start()
+                    .ok()
+                    .flatten()
+                    .map_or(value.len(), |e| e as usize)
+                    .clamp(start, value.len());
+                let inserted: Vec<u16> = format!("@{} ", name).encode_utf16().collect();
+                let caret = (start + inserted.len()) as u32;
+                value.splice(start..end, inserted);
+                input.set_value(&String::from_utf16_lossy(&value));
+                let _ = input.focus();
+                let _ = input.set_selection_range(caret, caret);
+                ctx.link().send_message(Msg::InputChanged);
+                true
+            }
+            Msg::CloseMentions => self.mention_token.take().is_some(),
+            Msg::TypingExpired(name) => self.typing.remove(&name).is_some(),
+            Msg::Scrolled => {
+                let len = self.visible_messages().len();
+                let before = self.render_window(len);
+                if let Some(list) = self.message_list.cast::<Element>() {
+                    let distance = list.scroll_height() - list.scroll_top() - list.client_height();
+                    self.stick_to_bottom = distance <= STICK_THRESHOLD_PX;
+                    self.scroll_top = list.scroll_top();
+                    self.viewport_height = list.client_height();
+                }
+                let window_moved = len > VIRTUALIZE_THRESHOLD && self.render_window(len) != before;
+                if self.read_until.is_some() && self.new_divider_scrolled_past() {
+                    self.read_until = None;
+                    self.new_divider_timeout = None;
+                    return true;
+                }
+                if self.scroll_top <= HISTORY_TRIGGER_PX && self.request_history(ctx) {
+                    return true;
+                }
+                if self.stick_to_bottom && self.unread > 0 {
+                    self.unread = 0;
+                    return true;
+                }
+                window_moved
+            }
+            Msg::PageUnload => {
+                self.wss.leave(&self.username);
+                false
+            }
+            Msg::Logout => {
+                self.wss.leave(&self.username);
+
+                self.messages.clear();
+                self.users.clear();
+                self.user.sign_out();
+                if let Some(history) = ctx.link().history() {
+                    history.push(Route::Login);
+                }
+                true
+            }
+            Msg::OpenConversation(peer) => {
+                self.sidebar_open = false;
+                self.profile_card = None;
+                if peer == self.dm_peer || peer.as_ref() == Some(&self.username) {
+                    // Still re-render so the drawer closes.
+                    return true;
+                }
+                self.switch_conversation(peer);
+                true
+            }
+            Msg::JoinRoom(room) => {
+                self.sidebar_open = false;
+                if self.dm_peer.is_some() {
+                    self.switch_conversation(None);
+                }
+                if room == self.current_room {
+                    return true;
+                }
+                let message = WebSocketMessage {
+                    room: Some(room.clone()),
+                    ..WebSocketMessage::new(MsgTypes::JoinRoom, Some(self.username.clone()))
+                };
+                let _ = self
+                    .wss
+                    .tx
+                    .clone()
+                    .try_send(serde_json::to_string(&message).unwrap());
+                // Rejoin the same room if the socket has to reconnect.
+                let register = WebSocketMessage {
+                    room: Some(room.clone()),
+                    avatar_style: Some(self.user.avatar_style.borrow().clone()),
+                    ..WebSocketMessage::new(MsgTypes::Register, Some(self.username.clone()))
+                };
+                self.wss.register(serde_json::to_string(&register).unwrap());
+
+                self.current_room = room;
+                self.editing = None;
+                self.reactions.clear();
+                self.pinned.clear();
+                self.reaction_picker = None;
+                self.replying_to = None;
+                self.restore_draft();
+                self.messages.clear();
+                self.history_request = None;
+                self.history_exhausted = false;
+                self.history_anchor = None;
+                self.typing.clear();
+                self.unread = 0;
+                self.stick_to_bottom = true;
+                true
+            }
+            Msg::ToggleMarkdown => {
+                self.settings.markdown = !self.settings.markdown;
+                self.save_settings(ctx);
+                true
+            }
+            Msg::VisibilityChanged => {
+                if notifications::page_hidden() {
+                    self.read_until = Some(js_sys::Date::now());
+                    self.new_divider_timeout = None;
+                    return false;
+                }
+                if self.hidden_unread > 0 {
+                    self.hidden_unread = 0;
+                    self.update_title();
+                }
+                if self.read_until.is_some() {
+                    let link = ctx.link().clone();
+                    self.new_divider_timeout =
+                        Some(Timeout::new(NEW_DIVIDER_LINGER_MS, move || {
+                            link.send_message(Msg::ClearNewDivider)
+                        }));
+                }
+                false
+            }
+            Msg::Activity => {
+                self.idle_timer = Self::idle_timer(ctx);
+                if !self.away {
+                    return false;
+                }
+                self.away = false;
+                self.away_users.remove(&self.username);
+                self.send_presence(false);
+                true
+            }
+            Msg::WentIdle => {
+                self.away = true;
+                self.away_users.insert(self.username.clone());
+                self.send_presence(true);
+                true
+            }
+            Msg::Reconnect => {
+                self.wss.reconnect_now();
+                false
+            }
+            Msg::NetworkChanged => {
+                let offline = !gloo::utils::window().navigator().on_line();
+                if offline == self.offline {
+                    return false;
+                }
+                self.offline = offline;
+                if !offline {
+                    // No point waiting out a backoff that started while offline.
+                    self.wss.reconnect_now();
+                }
+                true
+            }
+            Msg::ClearNewDivider => {
+                self.new_divider_timeout = None;
+                self.read_until.take().is_some()
+            }
+            Msg::ClearMessages => {
+                let confirmed = web_sys::window()
+                    .and_then(|w| w.confirm_with_message("Clear the messages in this conversation? This only affects your screen.").ok())
+                    .unwrap_or(false);
+                if !confirmed {
+                    return false;
+                }
+                self.messages.clear();
+                self.reactions.clear();
+                self.pinned.clear();
+                self.reaction_picker = None;
+                self.replying_to = None;
+                if self.editing.take().is_some() {
+                    self.clear_input();
+                }
+                self.unread = 0;
+                self.stick_to_bottom = true;
+                true
+            }
+            Msg::ToggleSettings => {
+                self.settings_open = !self.settings_open;
+                true
+            }
+            Msg::UpdateSettings(settings) => {
+                if settings.font_size != self.settings.font_size {
+                    settings.font_size.apply();
+                }
+                if settings.sound_muted != self.settings.sound_muted {
+                    self.sound_unlocked = true;
+                }
+                self.settings = settings;
+                self.save_settings(ctx);
+                true
+            }
+            Msg::ToggleExportMenu => {
+                self.export_menu_open = !self.export_menu_open;
+                true
+            }
+            Msg::Export(format) => {
+                self.export_menu_open = false;
+                let name = match &self.dm_peer {
+                    Some(peer) => format!("dm-{}", peer),
+                    None => self.current_room.clone(),
+                };
+                // Exports what's on screen, so muted users and search
+                // misses are left out.
+                if let Err(e) = export::download(
+                    self.visible_messages().into_iter(),
+                    format,
+                    &export::filename(&name, format),
+                ) {
+                    web_sys::console::warn_2(&"Couldn't export the chat log:".into(), &e);
+                }
+                true
+            }
+            Msg::ToggleSidebar => {
+                self.sidebar_open = !self.sidebar_open;
+                true
+            }
+            Msg::ToggleTheme => {
+                self.settings.theme = self.settings.theme.toggled();
+                self.save_settings(ctx);
+                true
+            }
+            Msg::RecallLastMessage => {
+                let last = self.messages.iter().rev().find(|m| {
+                    m.from == self.username && m.kind == MessageKind::User && m.id.is_some()
+                });
+                match last.and_then(|m| m.id.clone()) {
+                    Some(id) => self.begin_edit(id),
+                    None => false,
+                }
+            }
+            Msg::StartEdit(id) => self.begin_edit(id),
+            Msg::ReplyTo(id) => {
+                self.replying_to = Some(id);
+                if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+                    let _ = input.focus();
+                }
+                true
+            }
+            Msg::DismissToast(id) => {
+                let before = self.toasts.len();
+                self.toasts.retain(|t| t.id != id);
+                self.toasts.len() != before
+            }
+            Msg::CopyMessage(id) => {
+                let Some(text) = self
+                    .messages
+                    .iter()
+                    .find(|m| m.id.as_ref() == Some(&id))
+                    .map(|m| m.message.clone())
+                else {
+                    return false;
+                };
+                ctx.link().send_future(async move {
+                    let copied = clipboard::write_text(&text).await.is_ok();
+                    Msg::Copied(id, copied)
+                });
+                false
+            }
+            Msg::Copied(id, copied) => {
+                if !copied {
+                    clipboard::select_element(&message_text_element_id(&id));
+                }
+                self.copied = Some((id, copied));
+                let link = ctx.link().clone();
+                self.copied_reset = Some(Timeout::new(COPIED_NOTICE_MS, move || {
+                    link.send_message(Msg::ClearCopied)
+                }));
+                true
+            }
+            Msg::ClearCopied => self.copied.take().is_some(),
+            Msg::CancelReply => self.replying_to.take().is_some(),
+            Msg::ScrollToMessage(id) => {
+                if let Some(element) =
+                    gloo::utils::document().get_element_by_id(&message_element_id(&id))
+                {
+                    reveal(&element);
+                    self.highlight(ctx, id);
+                    return true;
+                }
+                // Outside the rendered window: jump to its estimated offset first.
+                let index = self
+                    .visible_messages()
+                    .iter()
+                    .position(|m| m.id.as_ref() == Some(&id));
+                match (index, self.message_list.cast::<Element>()) {
+                    (Some(index), Some(list)) => {
+                        list.set_scroll_top(
+                            index as i32 * self.settings.density.estimated_row_px(),
+                        );
+                        self.scroll_top = list.scroll_top();
+                        self.reveal_pending = Some(id.clone());
+                        self.highlight(ctx, id);
+                        true
+                    }
+                    // Loaded, just hidden because its sender is muted or it
+                    // doesn't match the search.
+                    _ if self.messages.iter().any(|m| m.id.as_ref() == Some(&id)) => false,
+                    _ => {
+                        if self.request_history(ctx) {
+                            self.jump_pending = Some(id);
+                        } else {
+                            self.show_toast(
+                                ctx,
+                                ToastKind::Warning,
+                                "Original message unavailable.".to_string(),
+                            );
+                        }
+                        true
+                    }
+                }
+            }
+            Msg::ClearHighlight => {
+                self.highlight_reset = None;
+                self.highlighted.take().is_some()
+            }
+            Msg::ToggleReactionPicker(id) => {
+                self.reaction_picker = match self.reaction_picker.take() {
+                    Some(open) if open == id => None,
+                    _ => Some(id),
+                };
+                true
+            }
+            Msg::TogglePin(id) => {
+                let pinned = !self.pinned.contains(&id);
+                let message = WebSocketMessage {
+                    id: Some(id.clone()),
+                    room: Some(self.current_room.clone()),
+                    from: Some(self.username.clone()),
+                    ..WebSocketMessage::new(
+                        MsgTypes::Pin,
+                        Some(if pinned { "pin" } else { "unpin" }.to_string()),
+                    )
+                };
+                let _ = self
+                    .wss
+                    .tx
+                    .clone()
+                    .try_send(serde_json::to_string(&message).unwrap());
+                self.set_pinned(id, pinned)
+            }
+            Msg::RetrySend(id) => {
+                let Some(index) = self.messages.iter().position(|m| {
+                    m.status == SendStatus::Failed && m.id.as_deref() == Some(id.as_str())
+                }) else {
+                    return false;
+                };
+                let sent = self.transmit(&self.messages[index]);
+                self.messages[index].status = if sent {
+                    SendStatus::Sending
+                } else {
+                    SendStatus::Failed
+                };
+                sent
+            }
+            Msg::TogglePinnedPanel => {
+                self.pinned_panel_open = !self.pinned_panel_open;
+                true
+            }
+            Msg::React { id, emoji } => {
+                let reaction = WebSocketMessage {
+                    id: Some(id.clone()),
+                    room: Some(self.current_room.clone()),
+                    from: Some(self.username.clone()),
+                    ..WebSocketMessage::new(MsgTypes::Reaction, Some(emoji.clone()))
+                };
+                let _ = self
+                    .wss
+                    .tx
+                    .clone()
+                    .try_send(serde_json::to_string(&reaction).unwrap());
+                self.reaction_picker = None;
+                let username = self.username.clone();
+                self.toggle_reaction(id, emoji, username);
+                true
+            }
+            Msg::DeleteMessage(id) => {
+                let own = self
+                    .messages
+                    .iter()
+                    .any(|m| m.id.as_ref() == Some(&id) && m.from == self.username);
+                if !own {
+                    return false;
+                }
+                let delete = WebSocketMessage {
+                    id: Some(id.clone()),
+                    room: Some(self.current_room.clone()),
+                    ..WebSocketMessage::new(MsgTypes::Delete, Some(self.username.clone()))
+                };
+                let _ = self
+                    .wss
+                    .tx
+                    .clone()
+                    .try_send(serde_json::to_string(&delete).unwrap());
+                let username = self.username.clone();
+                self.remove_message(&id, &username)
+            }
+            Msg::CancelEdit => {
+                if self.editing.take().is_none() {
+                    return false;
+                }
+                self.clear_input();
+                true
+            }
+            Msg::FocusInput => {
+                if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+                    let _ = input.focus();
+                }
+                false
+            }
+            Msg::DismissInput => {
+                self.clear_input();
+                if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+                    let _ = input.blur();
+                }
+                true
+            }
+            Msg::HistoryTimedOut => {
+                self.history_request = None;
+                self.history_exhausted = true;
+                if self.jump_pending.take().is_some() {
+                    self.show_toast(
+                        ctx,
+                        ToastKind::Warning,
+                        "Original message unavailable.".to_string(),
+                    );
+                }
+                true
+            }
+            Msg::Tick => !self.messages.is_empty(),
+            Msg::AvatarFailed(url) => self.broken_avatars.insert(url),
+            Msg::ImageLoaded(url) => self.loaded_images.insert(url),
+            Msg::ImageFailed(url) => self.broken_images.insert(url),
+            Msg::ToggleGifPicker => {
+                self.gif_picker_open = !self.gif_picker_open;
+                true
+            }
+            Msg::SearchGifs => {
+                let query = self
+                    .gif_input
+                    .cast::<HtmlInputElement>()
+                    .map(|i| i.value().trim().to_string())
+                    .unwrap_or_default();
+                if query.is_empty() {
+                    return false;
+                }
+                self.gif_search_seq += 1;
+                self.gif_loading = true;
+                let seq = self.gif_search_seq;
+                ctx.link()
+                    .send_future(async move { Msg::GifResults(seq, giphy::search(&query).await) });
+                true
+            }
+            Msg::GifResults(seq, results) => {
+                if seq != self.gif_search_seq {
+                    return false;
+                }
+                self.gif_loading = false;
+                self.gif_results = Some(results);
+                true
+            }
+            Msg::SendGif(url) => {
+                self.gif_picker_open = false;
+                self.send_message(url, MessageKind::User);
+                true
+            }
+            Msg::UploadImages(files) => {
+                self.drag_over = false;
+                if !upload::is_configured() {
+                    self.show_toast(
+                        ctx,
+                        ToastKind::Warning,
+                        UploadError::NotConfigured.to_string(),
+                    );
+                    return true;
+                }
+                let (images, others): (Vec<File>, Vec<File>) =
+                    files.into_iter().partition(upload::is_image);
+                if !others.is_empty() {
+                    self.show_toast(ctx, ToastKind::Warning, UploadError::NotAnImage.to_string());
+                }
+                for file in images {
+                    let id = self.next_upload_id;
+                    self.next_upload_id = self.next_upload_id.wrapping_add(1);
+                    start_upload(ctx, id, file.clone());
+                    let preview = Url::create_object_url_with_blob(&file).unwrap_or_default();
+                    self.uploads.push(PendingUpload {
+                        id,
+                        file,
+                        preview,
+                        error: None,
+                    });
+                    self.scroll_pending = true;
+                }
+                true
+            }
+            Msg::UploadFinished { id, result } => {
+                // Gone if it was discarded while uploading.
+                let Some(index) = self.uploads.iter().position(|u| u.id == id) else {
+                    return false;
+                };
+                match result {
+                    Ok(url) => {
+                        let _ = Url::revoke_object_url(&self.uploads.remove(index).preview);
+                        self.send_message(url, MessageKind::User);
+                    }
+                    Err(error) => self.uploads[index].error = Some(error),
+                }
+                true
+            }
+            Msg::RetryUpload(id) => match self.uploads.iter_mut().find(|u| u.id == id) {
+                Some(pending) => {
+                    pending.error = None;
+                    start_upload(ctx, id, pending.file.clone());
+                    true
+                }
+                None => false,
+            },
+            Msg::DiscardUpload(id) => match self.uploads.iter().position(|u| u.id == id) {
+                Some(index) => {
+                    let _ = Url::revoke_object_url(&self.uploads.remove(index).preview);
+                    true
+                }
+                None => false,
+            },
+            Msg::DragOver(over) => std::mem::replace(&mut self.drag_over, over) != over,
+            Msg::ToggleEmojiPicker => {
+                if !self.emoji_picker_listeners.is_empty() {
+                    self.emoji_picker_listeners.clear();
+                    return true;
+                }
+                let document = gloo::utils::document();
+                let link = ctx.link().clone();
+                let picker = self.emoji_picker.clone();
+                let outside_click = EventListener::new(&document, "mousedown", move |e| {
+                    let target = e.target().and_then(|t| t.dyn_into::<Node>().ok());
+                    let inside = picker.get().is_some_and(|p| p.contains(target.as_ref()));
+                    if !inside {
+                        link.send_message(Msg::CloseEmojiPicker);
+                    }
+                });
+                let link = ctx.link().clone();
+                let escape = EventListener::new(&document, "keydown", move |e| {
+                    if e.dyn_ref::<KeyboardEvent>()
+                        .is_some_and(|k| k.key() == "Escape")
+                    {
+                        link.send_message(Msg::CloseEmojiPicker);
+                    }
+                });
+                self.emoji_picker_listeners = vec![outside_click, escape];
+                true
+            }
+            Msg::CloseEmojiPicker => {
+                let was_open = !self.emoji_picker_listeners.is_empty();
+                self.emoji_picker_listeners.clear();
+                was_open
+            }
+            Msg::InsertEmoji(emoji) => {
+                if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+                    // Selection offsets are in UTF-16 code units, not bytes.
+                    let mut value: Vec<u16> = input.value().encode_utf16().collect();
+                    let start = input
+                        .selection_start()
+                        .ok()
+                        .flatten()
+                        .map_or(value.len(), |s| s as usize)
+                        .min(value.len());
+                    let end = input
+                        .selection_end()
+                        .ok()
+                        .flatten()
+                        .map_or(start, |e| e as usize)
+                        .clamp(start, value.len());
+                    let inserted: Vec<u16> = emoji.encode_utf16().collect();
+                    let caret = (start + inserted.len()) as u32;
+                    value.splice(start..end, inserted);
+                    input.set_value(&String::from_utf16_lossy(&value));
+                    let _ = input.focus();
+                    let _ = input.set_selection_range(caret, caret);
+                    ctx.link().send_message(Msg::InputChanged);
+                }
+                false
+            }
+            Msg::ToggleSearch => {
+                self.search_open = !self.search_open;
+                if !self.search_open {
+                    self.search_query.clear();
+                    self.search_debounce = None;
+                }
+                true
+            }
+            Msg::SearchInput => {
+                let link = ctx.link().clone();
+                self.search_debounce = Some(Timeout::new(SEARCH_DEBOUNCE_MS, move || {
+                    link.send_message(Msg::ApplySearch)
+                }));
+                false
+            }
+            Msg::ApplySearch => {
+                self.search_debounce = None;
+                let query = self
+                    .search_input
+                    .cast::<HtmlInputElement>()
+                    .map(|i| i.value().trim().to_lowercase())
+                    .unwrap_or_default();
+                let changed = query != self.search_query;
+                self.search_query = query;
+                changed
+            }
+            Msg::ToggleSound => {
+                self.settings.sound_muted = !self.settings.sound_muted;
+                self.sound_unlocked = true;
+                self.save_settings(ctx);
+                true
+            }
+            Msg::OpenMessageMenu { id, x, y } => {
+                self.message_menu = Some((id, x, y));
+                self.menu_from_keyboard = false;
+                true
+            }
+            Msg::CloseMessageMenu => {
+                let Some((id, _, _)) = self.message_menu.take() else {
+                    return false;
+                };
+                if std::mem::take(&mut self.menu_from_keyboard) {
+                    self.focus_pending = Some(id);
+                }
+                true
+            }
+            Msg::MessageFocused(id) => {
+                self.focused_message = Some(id);
+                false
+            }
+            Msg::MessageListKey(e) => {
+                if is_editable(e.target()) {
+                    return false;
+                }
+                let ids: Vec<&String> = self
+                    .visible_messages()
+                    .into_iter()
+                    .filter_map(|m| m.id.as_ref())
+                    .collect();
+                let Some(last) = ids.len().checked_sub(1) else {
+                    return false;
+                };
+                let current = self
+                    .focused_message
+                    .as_ref()
+                    .and_then(|f| ids.iter().position(|id| *id == f));
+                let target = match e.key().as_str() {
+                    "ArrowUp" => current.map_or(last, |i| i.saturating_sub(1)),
+                    "ArrowDown" => current.map_or(last, |i| (i + 1).min(last)),
+                    "Home" => 0,
+                    "End" => last,
+                    "Enter" | "ContextMenu" => {
+                        let Some(id) = current.map(|i| ids[i].clone()) else {
+                            return false;
+                        };
+                        // Enter on a button inside the message should press the button.
+                        let Some(element) = e
+                            .target()
+                            .and_then(|t| t.dyn_into::<Element>().ok())
+                            .filter(|t| t.id() == message_element_id(&id))
+                        else {
+                            return false;
+                        };
+                        e.prevent_default();
+                        let rect = element.get_bounding_client_rect();
+                        self.message_menu =
+                            Some((id, rect.left() as i32 + 16, rect.top() as i32 + 16));
+                        self.menu_from_keyboard = true;
+                        return true;
+                    }
+                    _ => return false,
+                };
+                e.prevent_default();
+                let id = ids[target].clone();
+                self.focused_message = Some(id.clone());
+                if gloo::utils::document()
+                    .get_element_by_id(&message_element_id(&id))
+                    .is_none()
+                {
+                    // Outside the rendered window: jump to its estimated offset first.
+                    if let Some(list) = self.message_list.cast::<Element>() {
+                        let index = self
+                            .visible_messages()
+                            .iter()
+                            .position(|m| m.id.as_ref() == Some(&id))
+                            .unwrap_or(0);
+                        list.set_scroll_top(
+                            index as i32 * self.settings.density.estimated_row_px(),
+                        );
+                        self.scroll_top = list.scroll_top();
+                    }
+                }
+                self.focus_pending = Some(id);
+                true
+            }
+            Msg::ShowProfile { name, x, y } => {
+                self.profile_hide = None;
+                self.profile_card = Some((name, x, y));
+                true
+            }
+            Msg::HideProfileSoon => {
+                let link = ctx.link().clone();
+                self.profile_hide = Some(Timeout::new(PROFILE_HIDE_DELAY_MS, move || {
+                    link.send_message(Msg::HideProfile)
+                }));
+                false
+            }
+            Msg::KeepProfile => {
+                self.profile_hide = None;
+                false
+            }
+            Msg::HideProfile => {
+                self.profile_hide = None;
+                self.profile_card.take().is_some()
+            }
+            Msg::ToggleGroupUsers => {
+                self.settings.group_users = !self.settings.group_users;
+                self.save_settings(ctx);
+                true
+            }
+            Msg::ToggleMute(name) => {
+                if !self.muted_users.remove(&name) {
+                    self.muted_users.insert(name);
+                }
+                self.save_muted_users();
+                true
+            }
+            Msg::UnmuteAll => {
+                self.muted_users.clear();
+                self.save_muted_users();
+                true
+            }
+            Msg::ScrollToBottom => {
+                if let Some(list) = self.message_list.cast::<Element>() {
+                    let mut options = ScrollToOptions::new();
+                    options
+                        .top(list.scroll_height() as f64)
+                        .behavior(ScrollBehavior::Smooth);
+                    list.scroll_to_with_scroll_to_options(&options);
+                }
+                self.stick_to_bottom = true;
+                self.unread = 0;
+                true
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let submit = ctx.link().callback(|_| Msg::SubmitMessage);
+        let onscroll = ctx.link().callback(|_: Event| Msg::Scrolled);
+        let ondragover = ctx.link().batch_callback(|e: DragEvent| {
+            let carries_files = e
+                .data_transfer()
+                .is_some_and(|d| d.types().includes(&JsValue::from_str("Files"), 0));
+            // Cancelling dragover is what allows the drop.
+            carries_files.then(|| {
+                e.prevent_default();
+                Msg::DragOver(true)
+            })
+        });
+        let ondragleave = {
+            let list = self.message_list.clone();
+            ctx.link().batch_callback(move |e: DragEvent| {
+                // Moving onto a message inside the list still counts as over it.
+                let into = e.related_target().and_then(|t| t.dyn_into::<Node>().ok());
+                let inside = list.get().is_some_and(|l| l.contains(into.as_ref()));
+                (!inside).then_some(Msg::DragOver(false))
+            })
+        };
+        let ondrop = ctx.link().callback(|e: DragEvent| {
+            e.prevent_default();
+            Msg::UploadImages(files_in(e.data_transfer()))
+        });
+        let onpaste = ctx.link().batch_callback(|e: Event| {
+            // `ClipboardEvent` is behind web-sys's unstable APIs, so read the field directly.
+            let data = js_sys::Reflect::get(&e, &JsValue::from_str("clipboardData"))
+                .ok()
+                .and_then(|d| d.dyn_into().ok());
+            let files = files_in(data);
+            let images: Vec<File> = files.into_iter().filter(upload::is_image).collect();
+            // Text pastes into the input as usual.
+            (!images.is_empty()).then(|| {
+                e.prevent_default();
+                Msg::UploadImages(images)
+            })
+        });
+        let scroll_to_bottom = ctx.link().callback(|_| Msg::ScrollToBottom);
+        let logout = ctx.link().callback(|_| Msg::Logout);
+        let toggle_markdown = ctx.link().callback(|_| Msg::ToggleMarkdown);
+        let toggle_sound = ctx.link().callback(|_| Msg::ToggleSound);
+        let toggle_search = ctx.link().callback(|_| Msg::ToggleSearch);
+        let toggle_theme = ctx.link().callback(|_| Msg::ToggleTheme);
+        let clear_messages = ctx.link().callback(|_| Msg::ClearMessages);
+        let toggle_emoji_picker = ctx.link().callback(|_| Msg::ToggleEmojiPicker);
+        let theme = self.settings.theme;
+        let search_input = ctx.link().callback(|_: InputEvent| Msg::SearchInput);
+        let oninput = ctx.link().callback(|_: InputEvent| Msg::InputChanged);
+        let completing = !self.mention_suggestions().is_empty();
+        let editing = self.editing.is_some();
+        let onkeydown = ctx.link().batch_callback(move |e: KeyboardEvent| {
+            if completing {
+                let msg = match e.key().as_str() {
+                    "ArrowDown" => Some(Msg::MoveMentionSelection(1)),
+                    "ArrowUp" => Some(Msg::MoveMentionSelection(-1)),
+                    "Enter" | "Tab" => Some(Msg::CompleteMention(None)),
+                    "Escape" => Some(Msg::CloseMentions),
+                    _ => None,
+                };
+                if msg.is_some() {
+                    e.prevent_default();
+                    return msg;
+                }
+            }
+            match e.key().as_str() {
+                // Shift+Enter falls through and inserts a newline.
+                "Enter" if !e.shift_key() => {
+                    e.prevent_default();
+                    Some(Msg::SubmitMessage)
+                }
+                "ArrowUp"
+                    if e.target_unchecked_into::<HtmlTextAreaElement>()
+                        .value()
+                        .is_empty() =>
+                {
+                    e.prevent_default();
+                    Some(Msg::RecallLastMessage)
+                }
+                "Escape" if editing => Some(Msg::CancelEdit),
+                "Escape" => Some(Msg::DismissInput),
+                _ => None,
+            }
+        });
+        let (status_color, status_label) = match self.wss.state() {
+            ConnectionState::Connected => ("bg-green-500", "Connected"),
+            ConnectionState::Connecting => ("bg-yellow-400", "Connecting"),
+            ConnectionState::Reconnecting => ("bg-orange-400", "Reconnecting"),
+            ConnectionState::Disconnected => ("bg-red-500", "Disconnected"),
+        };
+
+        html! {
+            <div class={classes!("flex", "w-screen", theme.page())}>
+                {
+                    if self.sidebar_open {
+                        html! { <div onclick={ctx.link().callback(|_| Msg::ToggleSidebar)} class="fixed inset-0 z-20 bg-black/40 md:hidden"></div> }
+                    } else {
+                        html! {}
+                    }
+                }
+                // A drawer below the `md` breakpoint, a fixed column above it.
+                <div class={classes!("flex-none", "w-56", "h-[100dvh]", "overflow-y-auto", "backdrop-blur", theme.sidebar(),
+                    "fixed", "inset-y-0", "left-0", "z-30", "transition-transform", "md:static", "md:translate-x-0",
+                    if self.sidebar_open { "translate-x-0" } else { "-translate-x-full" })}>
+                    <div class={classes!("flex", "items-center", "justify-between", "text-xl", "px-3", "pt-3", "pb-3.5", "font-semibold", "border-l-2", "border-b-2", theme.sidebar_header())}>
+                        <div class="flex items-center gap-2">
+                            <span>{"Users"}</span>
+                            <span class={classes!("text-xs", "font-semibold", "px-2", "py-0.5", "rounded-full", theme.chip())} title="Users online">{self.users.len()}</span>
+                        </div>
+                        <button onclick={logout} title="Log out" class={classes!("text-xs", "font-normal", "px-2", "py-1", "rounded-full", "transition-colors", theme.chip())}>{"Log out"}</button>
+                    </div>
+                    <div class="px-3 pt-3">
+                        <div class={classes!("text-xs", "font-semibold", "uppercase", "mb-1", theme.muted())}>{"Rooms"}</div>
+                        {
+                            ROOMS.iter().map(|room| {
+                                let active = *room == self.current_room && self.dm_peer.is_none();
+                                let onclick = ctx.link().callback(move |_| Msg::JoinRoom(room.to_string()));
+                                html! {
+                                    <button {onclick} class={classes!("block", "w-full", "text-left", "text-sm", "px-2", "py-1", "rounded-md", "transition-colors", theme.room(active))}>
+                                        {format!("# {}", room)}
+                                    </button>
+                                }
+                            }).collect::<Html>()
+                        }
+                    </div>
+                    {
+                        if self.users.is_empty() {
+                            html! { <div class={classes!("px-3", "py-6", "text-center", "text-sm", "italic", theme.muted())}>{"Waiting for users…"}</div> }
+                        } else {
+                            html! {
+                                <div class={classes!("flex", "items-center", "justify-between", "px-3", "pt-3", "text-xs", "font-semibold", "uppercase", theme.muted())}>
+                                    {"People"}
+                                    <button onclick={ctx.link().callback(|_| Msg::ToggleGroupUsers)} title="Group people by first letter"
+                                        class={classes!("normal-case", "font-normal", "px-2", "rounded-full", theme.room(self.settings.group_users))}>
+                                        {"A–Z"}
+                                    </button>
+                                </div>
+                            }
+                        }
+                    }
+                    {
+                        self.users.iter().enumerate().map(|(i, u)| {
+                            let letter = section_letter(&u.name);
+                            let header = if self.settings.group_users && u.name != self.username
+                                && (i == 0 || self.users[i - 1].name == self.username || section_letter(&self.users[i - 1].name) != letter)
+                            {
+                                html! { <div class={classes!("px-4", "pt-2", "text-xs", "font-semibold", theme.muted())}>{letter}</div> }
+                            } else {
+                                html! {}
+                            };
+                            let peer = u.name.clone();
+                            let on_open = ctx.link().callback(move |_| Msg::OpenConversation(Some(peer.clone())));
+                            let name = u.name.clone();
+                            let on_toggle_mute = ctx.link().callback(move |_| Msg::ToggleMute(name.clone()));
+                            let color = self.user_color(&u.name);
+                            html! {
+                                <key={u.name.clone()}>
+                                {header}
+                                <UserCard name={u.name.clone()} avatar={self.render_avatar(ctx, &u.avatar, &u.name, &color, "w-12 h-12")} {color}
+                                    online={u.online} away={self.away_users.contains(&u.name)} active={self.dm_peer.as_ref() == Some(&u.name)} muted={self.muted_users.contains(&u.name)}
+                                    unread={self.dm_unread.get(&u.name).copied().unwrap_or(0)} is_self={u.name == self.username}
+                                    {theme} {on_open} {on_toggle_mute}
+                                    onmouseenter={self.profile_trigger(ctx, &u.name)} onmouseleave={ctx.link().callback(|_| Msg::HideProfileSoon)} />
+                                </>
+                            }
+                        }).collect::<Html>()
+                    }
+                </div>
+                // `dvh` tracks the visible viewport, keeping the input above mobile keyboards.
+                <div class="grow min-w-0 h-[100dvh] flex flex-col">
+                    <div class={classes!("w-full", "h-14", "flex", "items-center", "justify-between", "border-b-2", "border-l-2", theme.header())}>
+                        <div class="flex items-center">
+                            <button onclick={ctx.link().callback(|_| Msg::ToggleSidebar)} title="Show users and rooms" aria-label="Show users and rooms" class="md:hidden ml-3 text-xl leading-none">{"☰"}</button>
+                            <div class="text-xl p-3 font-semibold">{"UwU Cafee Chat"}</div>
+                            {
+                                match &self.dm_peer {
+                                    Some(peer) => html! {
+                                        <span class={classes!("text-sm", "mr-2", theme.muted())} title="Direct message">{format!("✉ @{}", peer)}</span>
+                                    },
+                                    None => html! {
+                                        <span class={classes!("text-sm", "mr-2", theme.muted())}>{format!("# {}", self.current_room)}</span>
+                                    },
+                                }
+                            }
+                            <span class={classes!("w-3", "h-3", "mr-2", "rounded-full", status_color)} title={format!("{} ({})", status_label, self.wss.url())}></span>
+                            {
+                                match self.wss.latency() {
+                                    Some(latency) if self.wss.is_connected() => {
+                                        let color = if latency >= HIGH_LATENCY_MS {
+                                            "text-red-600 font-semibold"
+                                        } else if latency >= SLOW_LATENCY_MS {
+                                            "text-amber-600"
+                                        } else {
+                                            theme.muted()
+                                        };
+                                        html! { <span class={classes!("text-xs", "mr-2", color)} title="Round-trip time to the server">{format!("{:.0} ms", latency)}</span> }
+                                    }
+                                    _ => html! {},
+                                }
+                            }
+                            {
+                                if let Some(attempt) = self.reconnect_attempt {
+                                    html! { <div class={classes!("text-xs", theme.muted())}>{format!("Reconnecting… (attempt {})", attempt)}</div> }
+                                } else {
+                                    html! {}
+                                }
+                            }
+                            {
+                                if self.wss.parse_failure_count() >= MALFORMED_WARNING_THRESHOLD {
+                                    let last_error = self.wss.parse_failures().last().map(|f| {
+                                        let payload: String = f.payload.chars().take(200).collect();
+                                        format!("{} {}: {}", format_time(f.at, self.settings.hour12), f.error, payload)
+                                    }).unwrap_or_default();
+                                    html! {
+                                        <div class="ml-2 text-xs text-amber-700" title={last_error}>
+                                            {"⚠ Some server messages couldn't be read"}
+                                        </div>
+                                    }
+                                } else {
+                                    html! {}
+                                }
+                            }
+                        </div>
+                        <div class="flex items-center gap-2 pr-3">
+                            <button onclick={toggle_search} title="Search messages" aria-label="Search messages" aria-pressed={self.search_open.to_string()} class={classes!("text-lg", "leading-none", "px-1", "rounded-full", "transition-colors", theme.toolbar_button(self.search_open))}>
+                                {"🔍"}
+                            </button>
+                            <button onclick={toggle_markdown} title="Toggle markdown formatting" aria-pressed={self.settings.markdown.to_string()} class={classes!("text-xs", "px-2", "py-1", "rounded-full", "transition-colors", theme.toolbar_button(self.settings.markdown))}>
+                                {"Markdown"}
+                            </button>
+                            <button onclick={toggle_sound} title={if self.settings.sound_muted { "Unmute notification sound" } else { "Mute notification sound" }} aria-label={if self.settings.sound_muted { "Unmute notification sound" } else { "Mute notification sound" }} class={classes!("text-lg", "leading-none", "px-1", "rounded-full", "transition-colors", theme.toolbar_button(false))}>
+                                { if self.settings.sound_muted { "🔕" } else { "🔔" } }
+                            </button>
+                            <div class="relative">
+                                <button onclick={ctx.link().callback(|_| Msg::ToggleExportMenu)} title="Download this conversation" aria-label="Download this conversation" aria-haspopup="menu" aria-expanded={self.export_menu_open.to_string()} class={classes!("text-lg", "leading-none", "px-1", "rounded-full", "transition-colors", theme.toolbar_button(self.export_menu_open))}>
+                                    {"⬇"}
+                                </button>
+                                {
+                                    if self.export_menu_open {
+                                        html! {
+                                            <div class={classes!("absolute", "right-0", "top-8", "z-10", "w-36", "py-1", "rounded-lg", "border", "shadow-lg", "text-sm", theme.input())}>
+                                                <button onclick={ctx.link().callback(|_| Msg::Export(ExportFormat::Text))} class="block w-full text-left px-3 py-1 hover:bg-pink-200">{"Plain text (.txt)"}</button>
+                                                <button onclick={ctx.link().callback(|_| Msg::Export(ExportFormat::Json))} class="block w-full text-left px-3 py-1 hover:bg-pink-200">{"JSON (.json)"}</button>
+                                            </div>
+                                        }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                            </div>
+                            <button onclick={ctx.link().callback(|_| Msg::ToggleSettings)} title="Settings" aria-label="Settings" aria-haspopup="dialog" aria-expanded={self.settings_open.to_string()} class={classes!("text-lg", "leading-none", "px-1", "rounded-full", "transition-colors", theme.toolbar_button(self.settings_open))}>
+                                {"⚙"}
+                            </button>
+                            <button onclick={clear_messages} title="Clear messages on this screen" aria-label="Clear messages on this screen" class={classes!("text-lg", "leading-none", "px-1", "rounded-full", "transition-colors", theme.toolbar_button(false))}>
+                                {"🧹"}
+                            </button>
+                            <button onclick={toggle_theme} title={if theme == Theme::Dark { "Switch to light mode" } else { "Switch to dark mode" }} aria-label={if theme == Theme::Dark { "Switch to light mode" } else { "Switch to dark mode" }} class={classes!("text-lg", "leading-none", "px-1", "rounded-full", "transition-colors", theme.toolbar_button(false))}>
+                                { if theme == Theme::Dark { "☀️" } else { "🌙" } }
+                            </button>
+                        </div>
+                    </div>
+                    {
+                        if self.search_open {
+                            html! {
+                                <div class={classes!("w-full", "px-4", "py-2", "border-l-2", "border-b", theme.header())}>
+                                    <input ref={self.search_input.clone()} oninput={search_input} type="search" placeholder="Search messages…" class={classes!("w-full", "text-sm", "border", "focus:border-blue-400", "rounded-full", "px-4", "py-1", theme.input())} />
+                                </div>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    {
+                        match self.reconnect_attempt {
+                            _ if self.offline => html! {
+                                <div class="w-full px-4 py-1 border-l-2 border-b text-center text-xs bg-gray-200 text-gray-800 border-gray-300">
+                                    {"You're offline. Messages you send will go out once you're back online."}
+                                </div>
+                            },
+                            Some(attempt) if self.ready => html! {
+                                <div class="w-full flex items-center justify-center gap-3 px-4 py-1 border-l-2 border-b text-xs bg-red-100 text-red-800 border-red-200">
+                                    {format!("Connection lost. Reconnecting… (attempt {})", attempt)}
+                                    {self.render_reconnect_button(ctx)}
+                                </div>
+                            },
+                            _ => html! {},
+                        }
+                    }
+                    {self.render_pinned(ctx)}
+                    <div class="relative grow min-h-0">
+                        <div ref={self.message_list.clone()} {onscroll} {ondragover} {ondragleave} {ondrop} tabindex="0" role="region" aria-label="Messages. Use the arrow keys to move between them."
+                            onkeydown={ctx.link().callback(Msg::MessageListKey)} class={classes!("w-full", "h-full", "overflow-auto", "border-l-1", "border-b-2", "px-4", "py-2", theme.panel())}>
+                            {
+                                if self.history_request.is_some() {
+                                    html! { <div class={classes!("text-center", "text-xs", "py-2", theme.muted())}>{"Loading older messages…"}</div> }
+                                } else {
+                                    html! {}
+                                }
+                            }
+                            {self.render_messages(ctx)}
+                            { for self.uploads.iter().map(|u| self.render_upload(ctx, u)) }
+                        </div>
+                        {
+                            if self.drag_over {
+                                html! {
+                                    <div class="absolute inset-0 pointer-events-none flex items-center justify-center border-4 border-dashed border-pink-400 bg-pink-50/70 text-pink-700 font-semibold">
+                                        {"Drop an image to send it"}
+                                    </div>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        <div role="log" aria-live="polite" aria-relevant="additions" class="sr-only">
+                            { for self.announcements.iter().map(|(id, text)| html! { <div key={*id}>{text.clone()}</div> }) }
+                        </div>
+                        {self.render_message_menu(ctx)}
+                        {self.render_profile_card(ctx)}
+                        {
+                            if self.settings_open {
+                                html! {
+                                    <SettingsPanel settings={self.settings} on_change={ctx.link().callback(Msg::UpdateSettings)}
+                                        on_close={ctx.link().callback(|_| Msg::ToggleSettings)} />
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        <div class="fixed bottom-20 right-4 z-50 flex flex-col items-end gap-2">
+                            { for self.toasts.iter().map(|t| {
+                                let id = t.id;
+                                let on_dismiss = ctx.link().callback(move |_| Msg::DismissToast(id));
+                                html! { <Toast key={id} kind={t.kind} message={t.message.clone()} {on_dismiss} /> }
+                            }) }
+                        </div>
+                        {
+                            if self.ready {
+                                html! {}
+                            } else if self.offline {
+                                html! {
+                                    <div class={classes!("absolute", "inset-0", "flex", "flex-col", "items-center", "justify-center", "gap-2", "text-sm", theme.panel())}>
+                                        <div class="font-semibold">{"You're offline"}</div>
+                                        <div class={theme.muted()}>{"The chat will connect when your network is back."}</div>
+                                    </div>
+                                }
+                            } else if let Some(attempt) = self.reconnect_attempt {
+                                html! {
+                                    <div class={classes!("absolute", "inset-0", "flex", "flex-col", "items-center", "justify-center", "gap-2", "text-sm", theme.panel())}>
+                                        <div class="text-red-500 font-semibold">{"Couldn't connect to the chat server"}</div>
+                                        <div class={theme.muted()}>{format!("Retrying… (attempt {})", attempt)}</div>
+                                        {self.render_reconnect_button(ctx)}
+                                    </div>
+                                }
+                            } else {
+                                html! {
+                                    <div class={classes!("absolute", "inset-0", "flex", "flex-col", "items-center", "justify-center", "gap-3", "text-sm", theme.panel(), theme.muted())}>
+                                        <div class="w-8 h-8 rounded-full border-4 border-pink-300 border-t-pink-600 animate-spin"></div>
+                                        {"Connecting…"}
+                                    </div>
+                                }
+                            }
+                        }
+                        {
+                            if self.unread > 0 {
+                                html! {
+                                    <button onclick={scroll_to_bottom} class="absolute bottom-4 right-4 flex items-center gap-1 bg-pink-500 hover:bg-pink-600 text-white text-xs font-semibold px-3 py-2 rounded-full shadow-lg transition-transform hover:scale-110">
+                                        <svg class="w-4 h-4 fill-current" viewBox="0 0 24 24"><path d="M12 16.5l-7-7 1.4-1.4 5.6 5.6 5.6-5.6 1.4 1.4z"/></svg>
+                                        {self.unread}
+                                    </button>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                    </div>
+                    {
+                        if let Some(text) = self.typing_text() {
+                            html! { <div class={classes!("w-full", "px-4", "py-1", "text-xs", "italic", "border-l-2", theme.panel(), theme.muted())}>{text}</div> }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    {
+                        if let Some(original) = self.replying_to.as_deref().and_then(|id| self.messages.iter().find(|m| m.id.as_deref() == Some(id))) {
+                            let cancel_reply = ctx.link().callback(|_| Msg::CancelReply);
+                            html! {
+                                <div class={classes!("w-full", "flex", "items-center", "justify-between", "px-4", "py-1", "text-xs", "border-l-2", theme.panel(), theme.muted())}>
+                                    <span class="truncate">{format!("Replying to {}: {}", original.from, preview(&original.message))}</span>
+                                    <button onclick={cancel_reply} title="Cancel reply" aria-label="Cancel reply" class="ml-2">{"✕"}</button>
+                                </div>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    {
+                        if self.editing.is_some() {
+                            html! { <div class={classes!("w-full", "px-4", "py-1", "text-xs", "border-l-2", theme.panel(), theme.muted())}>{"Editing message · Enter to save, Esc to cancel"}</div> }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    <div class={classes!("relative", "w-full", "min-h-14", "flex", "px-3", "py-2", "items-end", "border-l-2", "backdrop-blur", theme.input_bar())}>
+                        {
+                            if completing {
+                                html! {
+                                    <ul class={classes!("absolute", "bottom-full", "mb-1", "left-16", "z-10", "w-56", "py-1", "rounded-lg", "border", "shadow-lg", theme.input())}>
+                                        { for self.mention_suggestions().into_iter().enumerate().map(|(i, name)| {
+                                            let onmousedown = ctx.link().callback(move |e: MouseEvent| {
+                                                // Keep focus (and the caret) in the input.
+                                                e.prevent_default();
+                                                Msg::CompleteMention(Some(i))
+                                            });
+                                            html! {
+                                                <li {onmousedown} class={classes!("px-3", "py-1", "text-sm", "cursor-pointer",
+                                                    if i == self.mention_selected { "bg-pink-500 text-white" } else { "hover:bg-pink-200" })}>
+                                                    {format!("@{}", name)}
+                                                </li>
+                                            }
+                                        }) }
+                                    </ul>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        <div class="relative mr-1">
+                            <button onclick={ctx.link().callback(|_| Msg::ToggleGifPicker)} title="Send a GIF" class={classes!("text-xs", "font-bold", "px-2", "py-1", "rounded-full", "border", "transition-colors", theme.toolbar_button(self.gif_picker_open))}>
+                                {"GIF"}
+                            </button>
+                            { if self.gif_picker_open { self.render_gif_picker(ctx) } else { html! {} } }
+                        </div>
+                        <div ref={self.emoji_picker.clone()} class="relative mr-3">
+                            <button onclick={toggle_emoji_picker} title="Insert emoji" aria-label="Insert emoji" class={classes!("text-xl", "leading-none", "p-1", "rounded-full", "transition-colors", theme.toolbar_button(!self.emoji_picker_listeners.is_empty()))}>
+                                {"😊"}
+                            </button>
+                            {
+                                if self.emoji_picker_listeners.is_empty() {
+                                    html! {}
+                                } else {
+                                    html! {
+                                        <div class={classes!("absolute", "bottom-12", "left-0", "z-10", "w-72", "max-h-72", "overflow-y-auto", "p-2", "rounded-lg", "border", "shadow-lg", theme.input())}>
+                                            { for emoji::PICKER_CATEGORIES.iter().map(|(category, emojis)| html! {
+                                                <div class="mb-2">
+                                                    <div class={classes!("text-xs", "font-semibold", "mb-1", theme.muted())}>{*category}</div>
+                                                    <div class="grid grid-cols-8 gap-1">
+                                                        { for emojis.iter().map(|e| {
+                                                            let e: &'static str = e;
+                                                            let onclick = ctx.link().callback(move |_| Msg::InsertEmoji(e));
+                                                            html! { <button {onclick} class="text-xl rounded hover:bg-pink-200">{e}</button> }
+                                                        }) }
+                                                    </div>
+                                                </div>
+                                            }) }
+                                        </div>
+                                    }
+                                }
+                            }
+                        </div>
+                        <MessageInput input_ref={self.chat_input.clone()} {theme} draft_len={self.draft_len} max_len={MAX_MESSAGE_LEN}
+                            connected={self.wss.is_connected()} {oninput} {onkeydown} {onpaste} onsubmit={submit} />
+                    </div>
+                </div>
+            </div>
+        }
+    }
+
+    fn rendered(&mut self, _ctx: &Context<Self>, first_render: bool) {
+        if let Some(id) = self.reveal_pending.take() {
+            if let Some(element) =
+                gloo::utils::document().get_element_by_id(&message_element_id(&id))
+            {
+                reveal(&element);
+            }
+        }
+        if let Some(id) = self.focus_pending.take() {
+            if let Some(element) = gloo::utils::document()
+                .get_element_by_id(&message_element_id(&id))
+                .and_then(|e| e.dyn_into::<HtmlElement>().ok())
+            {
+                let _ = element.focus();
+            }
+        }
+        if first_render {
+            self.restore_draft();
+            // Only once, so later re-renders don't steal focus from other controls.
+            if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+                let _ = input.focus();
+            }
+        }
+        if let Some(anchor) = self.history_anchor.take() {
+            if let Some(list) = self.message_list.cast::<Element>() {
+                list.set_scroll_top(list.scroll_height() - anchor);
+            }
+        }
+        if self.scroll_pending {
+            self.scroll_pending = false;
+            if let Some(list) = self.message_list.cast::<Element>() {
+                list.set_scroll_top(list.scroll_height());
+            }
+        }
+    }
+
+    fn destroy(&mut self, _ctx: &Context<Self>) {
+        gloo::utils::document().set_title(&self.original_title);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: &str, from: &str, text: &str) -> MessageData {
+        MessageData {
+            from: from.to_string(),
+            message: text.to_string(),
+            timestamp: Some(0.0),
+            kind: MessageKind::User,
+            id: Some(id.to_string()),
+            room: None,
+            status: SendStatus::Sent,
+            edited: false,
+            reply_to: None,
+        }
+    }
+
+    fn ids(messages: &VecDeque<MessageData>) -> Vec<&str> {
+        messages.iter().filter_map(|m| m.id.as_deref()).collect()
+    }
+
+    #[test]
+    fn edit_replaces_only_the_matching_message() {
+        let mut messages: VecDeque<_> = vec![
+            message("1", "alice", "first"),
+            message("2", "bob", "second"),
+        ]
+        .into();
+
+        assert!(edit_by_id(&mut messages, "2", "changed".to_string()));
+        assert_eq!(messages[1].message, "changed");
+        assert!(messages[1].edited);
+        assert_eq!(messages[0].message, "first");
+        assert!(!messages[0].edited);
+    }
+
+    #[test]
+    fn edit_with_unknown_id_changes_nothing() {
+        let mut messages: VecDeque<_> = vec![message("1", "alice", "first")].into();
+
+        assert!(!edit_by_id(&mut messages, "9", "changed".to_string()));
+        assert_eq!(messages[0].message, "first");
+        assert!(!messages[0].edited);
+    }
+
+    #[test]
+    fn removal_takes_out_only_the_matching_message() {
+        let mut messages: VecDeque<_> = vec![
+            message("1", "alice", "first"),
+            message("2", "bob", "second"),
+            message("3", "alice", "third"),
+        ]
+        .into();
+
+        let removed = remove_by_id(&mut messages, "2", "bob");
+        assert_eq!(removed.and_then(|m| m.id), Some("2".to_string()));
+        assert_eq!(ids(&messages), ["1", "3"]);
+    }
+
+    #[test]
+    fn removal_needs_the_right_id_and_author() {
+        let mut messages: VecDeque<_> = vec![message("1", "alice", "first")].into();
+
+        assert!(remove_by_id(&mut messages, "1", "bob").is_none());
+        assert!(remove_by_id(&mut messages, "9", "alice").is_none());
+        assert_eq!(ids(&messages), ["1"]);
+    }
+
+    fn users(names: &[&str]) -> Vec<UserProfile> {
+        names
+            .iter()
+            .map(|name| UserProfile {
+                name: name.to_string(),
+                avatar: String::new(),
+                online: true,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mentions_stop_at_punctuation() {
+        assert_eq!(mention_spans("hi @bob, you there?"), [(3, 7)]);
+        assert_eq!(mention_spans("@bob!"), [(0, 4)]);
+        assert_eq!(extract_mentions("thanks @bob.", &users(&["bob"])), ["bob"]);
+    }
+
+    #[test]
+    fn email_addresses_are_not_mentions() {
+        assert!(mention_spans("mail bob@example.com").is_empty());
+        assert!(extract_mentions("mail alice@bob", &users(&["bob"])).is_empty());
+    }
+
+    #[test]
+    fn a_lone_at_sign_is_not_a_mention() {
+        assert!(mention_spans("meet @ noon").is_empty());
+        assert!(mention_spans("@").is_empty());
+    }
+
+    #[test]
+    fn repeated_mentions_are_listed_once() {
+        let users = users(&["alice", "bob"]);
+        assert_eq!(
+            extract_mentions("@bob @alice @bob", &users),
+            ["bob", "alice"]
+        );
+    }
+
+    #[test]
+    fn mentions_match_names_case_sensitively() {
+        let users = users(&["alice"]);
+        assert_eq!(mention_spans("@Alice"), [(0, 6)]);
+        assert!(extract_mentions("@Alice", &users).is_empty());
+        assert!(extract_mentions("@carol", &users).is_empty());
+    }
+
+    const SECOND: f64 = 1_000.0;
+    const NOW: f64 = 1_700_000_000_000.0;
+
+    fn ago(seconds: f64) -> String {
+        format_relative(NOW - seconds * SECOND, NOW)
+    }
+
+    #[test]
+    fn relative_time_boundaries() {
+        assert_eq!(ago(0.0), "just now");
+        assert_eq!(ago(59.0), "just now");
+        assert_eq!(ago(60.0), "1m");
+        assert_eq!(ago(59.0 * 60.0), "59m");
+        assert_eq!(ago(60.0 * 60.0), "1h");
+        assert_eq!(ago(23.0 * 3_600.0), "23h");
+        assert_eq!(ago(24.0 * 3_600.0), "1d");
+    }
+
+    #[test]
+    fn relative_time_rounds_down() {
+        assert_eq!(ago(59.999), "just now");
+        assert_eq!(ago(119.0), "1m");
+        assert_eq!(ago(86_399.0), "23h");
+    }
+
+    #[test]
+    fn future_timestamps_are_just_now() {
+        assert_eq!(ago(-5.0), "just now");
+        assert_eq!(ago(-86_400.0), "just now");
+    }
+
+    #[test]
+    fn limiter_refuses_once_the_burst_is_used() {
+        let mut limiter = RateLimiter::new(3, 1_000.0);
+        assert!(limiter.try_acquire(0.0));
+        assert!(limiter.try_acquire(10.0));
+        assert!(limiter.try_acquire(20.0));
+        assert!(!limiter.try_acquire(30.0));
+        assert!(!limiter.try_acquire(999.0));
+    }
+
+    #[test]
+    fn limiter_refills_as_the_window_slides() {
+        let mut limiter = RateLimiter::new(2, 1_000.0);
+        assert!(limiter.try_acquire(0.0));
+        assert!(limiter.try_acquire(500.0));
+        assert!(!limiter.try_acquire(600.0));
+
+        assert!(limiter.try_acquire(1_200.0));
+        assert!(!limiter.try_acquire(1_300.0));
+        assert!(limiter.try_acquire(3_000.0));
+        assert!(limiter.try_acquire(3_000.0));
+    }
+
+    #[test]
+    fn limiter_frees_a_slot_exactly_at_the_window_edge() {
+        let mut limiter = RateLimiter::new(1, 1_000.0);
+        assert!(limiter.try_acquire(0.0));
+        assert!(!limiter.try_acquire(999.999));
+        assert!(limiter.try_acquire(1_000.0));
+    }
+
+    #[test]
+    fn refused_sends_do_not_use_up_the_window() {
+        let mut limiter = RateLimiter::new(1, 1_000.0);
+        assert!(limiter.try_acquire(0.0));
+        assert!(!limiter.try_acquire(900.0));
+        assert!(limiter.try_acquire(1_000.0));
+    }
+
+    fn typers(count: usize) -> Vec<String> {
+        ["alice", "bob", "carol", "dave", "erin"][..count]
+            .iter()
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn typing_labels() {
+        assert_eq!(typing_label(&typers(0)), "");
+        assert_eq!(typing_label(&typers(1)), "alice is typing…");
+        assert_eq!(typing_label(&typers(2)), "alice and bob are typing…");
+        assert_eq!(typing_label(&typers(3)), "alice, bob and carol are typing…");
+        assert_eq!(
+            typing_label(&typers(4)),
+            "alice, bob and 2 others are typing…"
+        );
+        assert_eq!(
+            typing_label(&typers(5)),
+            "alice, bob and 3 others are typing…"
+        );
+    }
+
+    #[test]
+    fn message_cap_evicts_the_oldest() {
+        let mut messages = VecDeque::new();
+        let mut evicted = Vec::new();
+        for i in 0..MAX_MESSAGES + 10 {
+            evicted.extend(push_capped(
+                &mut messages,
+                message(&i.to_string(), "alice", "hi"),
+                MAX_MESSAGES,
+            ));
+        }
+
+        assert_eq!(messages.len(), MAX_MESSAGES);
+        assert_eq!(messages.front().and_then(|m| m.id.as_deref()), Some("10"));
+        let newest = (MAX_MESSAGES + 9).to_string();
+        assert_eq!(
+            messages.back().and_then(|m| m.id.as_deref()),
+            Some(newest.as_str())
+        );
+        let evicted: Vec<&str> = evicted.iter().filter_map(|m| m.id.as_deref()).collect();
+        assert_eq!(evicted, ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"]);
+    }
+
+    #[test]
+    fn under_the_cap_nothing_is_evicted() {
+        let mut messages = VecDeque::new();
+        assert!(push_capped(&mut messages, message("a", "alice", "hi"), 2).is_empty());
+        assert!(push_capped(&mut messages, message("b", "alice", "hi"), 2).is_empty());
+        assert_eq!(ids(&messages), ["a", "b"]);
+    }
+
+    #[test]
+    fn script_and_data_urls_are_unsafe() {
+        for url in [
+            "javascript:alert(1)",
+            "JaVaScRiPt:alert(1)",
+            " javascript:alert(1)",
+            "\tjavascript:alert(1)",
+            "\u{1}javascript:alert(1)",
+            "java\u{0}script:alert(1)",
+            "data:text/html,<script>alert(1)</script>",
+            "DATA:image/png;base64,AAAA",
+        ] {
+            assert!(!is_safe_url(url), "{:?} should be rejected", url);
+            assert!(!is_image_url(url), "{:?} should be rejected", url);
+        }
+    }
+
+    #[test]
+    fn malformed_http_urls_are_unsafe() {
+        for url in [
+            "http:",
+            "https://",
+            "http:example.com",
+            " https://example.com/a.png",
+            "https://example.com/a b.png",
+            "https://example.com/\"onerror=\".png",
+        ] {
+            assert!(!is_safe_url(url), "{:?} should be rejected", url);
+        }
+    }
+
+    #[test]
+    fn http_urls_pass() {
+        assert!(is_safe_url("http://example.com"));
+        assert!(is_safe_url("HTTPS://example.com/path?q=1"));
+        assert!(is_image_url("https://example.com/cat.PNG"));
+        assert!(is_image_url("http://example.com/cat.gif?size=small#top"));
+        assert!(!is_image_url("https://example.com/cat.html"));
+    }
 }
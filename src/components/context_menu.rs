@@ -0,0 +1,187 @@
+//! A popup list of actions, opened at the pointer or under a button.
+
+use gloo::events::EventListener;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, HtmlElement, Node};
+use yew::functional::*;
+use yew::prelude::*;
+
+/// Space kept between the menu and the edges of the viewport.
+const VIEWPORT_MARGIN_PX: f64 = 8.0;
+
+#[derive(Clone, PartialEq)]
+pub struct MenuItem {
+    pub label: String,
+    /// Shown in red, for destructive actions.
+    pub danger: bool,
+    pub on_select: Callback<()>,
+}
+
+impl MenuItem {
+    pub fn new(label: &str, on_select: Callback<()>) -> Self {
+        Self {
+            label: label.to_string(),
+            danger: false,
+            on_select,
+        }
+    }
+
+    pub fn danger(self) -> Self {
+        Self {
+            danger: true,
+            ..self
+        }
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ContextMenuProps {
+    /// Where to open, in viewport pixels. The menu moves inward if it
+    /// would otherwise overflow the window.
+    pub x: i32,
+    pub y: i32,
+    pub items: Vec<MenuItem>,
+    /// Called after an item is chosen, and on outside clicks, Esc or Tab.
+    pub on_close: Callback<()>,
+}
+
+/// Arrow keys, Home and End move between items; Enter or Space picks one.
+#[function_component(ContextMenu)]
+pub fn context_menu(props: &ContextMenuProps) -> Html {
+    let menu = use_node_ref();
+    let position = use_state(|| (props.x as f64, props.y as f64));
+
+    {
+        let menu = menu.clone();
+        let position = position.clone();
+        use_effect_with_deps(
+            move |&(x, y)| {
+                if let Some(element) = menu.cast::<HtmlElement>() {
+                    position.set(fit_in_viewport(&element, x, y));
+                    focus_item(&element, 0);
+                }
+                || ()
+            },
+            (props.x, props.y),
+        );
+    }
+
+    {
+        let menu = menu.clone();
+        let on_close = props.on_close.clone();
+        use_effect_with_deps(
+            move |_| {
+                let document = gloo::utils::document();
+                let close = on_close.clone();
+                let outside_click = EventListener::new(&document, "mousedown", move |e| {
+                    let target = e.target().and_then(|t| t.dyn_into::<Node>().ok());
+                    if !menu.get().is_some_and(|m| m.contains(target.as_ref())) {
+                        close.emit(());
+                    }
+                });
+                let escape = EventListener::new(&document, "keydown", move |e| {
+                    if e.dyn_ref::<KeyboardEvent>()
+                        .is_some_and(|k| k.key() == "Escape")
+                    {
+                        on_close.emit(());
+                    }
+                });
+                move || drop((outside_click, escape))
+            },
+            (),
+        );
+    }
+
+    let onkeydown = {
+        let menu = menu.clone();
+        let on_close = props.on_close.clone();
+        let count = props.items.len() as u32;
+        Callback::from(move |e: KeyboardEvent| {
+            let Some(element) = menu.cast::<Element>() else {
+                return;
+            };
+            if count == 0 {
+                return;
+            }
+            let current = focused_index(&element);
+            let next = match e.key().as_str() {
+                "ArrowDown" => current.map_or(0, |i| (i + 1) % count),
+                "ArrowUp" => current.map_or(count - 1, |i| (i + count - 1) % count),
+                "Home" => 0,
+                "End" => count - 1,
+                "Tab" => {
+                    e.prevent_default();
+                    on_close.emit(());
+                    return;
+                }
+                _ => return,
+            };
+            e.prevent_default();
+            focus_item(&element, next);
+        })
+    };
+
+    let (left, top) = *position;
+    html! {
+        <div ref={menu} role="menu" {onkeydown} style={format!("left:{}px; top:{}px", left, top)}
+            class="fixed z-40 min-w-[9rem] py-1 rounded-lg shadow-lg border bg-white border-gray-200 text-sm text-gray-800">
+            {
+                props.items.iter().map(|item| {
+                    let on_select = item.on_select.clone();
+                    let on_close = props.on_close.clone();
+                    let onclick = Callback::from(move |_| {
+                        on_select.emit(());
+                        on_close.emit(());
+                    });
+                    html! {
+                        <button role="menuitem" {onclick}
+                            class={classes!("block", "w-full", "text-left", "px-3", "py-1.5", "hover:bg-gray-100", "focus:bg-gray-100", "focus:outline-none",
+                                item.danger.then_some("text-red-600"))}>
+                            {item.label.clone()}
+                        </button>
+                    }
+                }).collect::<Html>()
+            }
+        </div>
+    }
+}
+
+/// Where to put the top-left corner of `element` so it opens at `(x, y)`
+/// but stays inside the window.
+pub fn fit_in_viewport(element: &Element, x: i32, y: i32) -> (f64, f64) {
+    let rect = element.get_bounding_client_rect();
+    let window = gloo::utils::window();
+    let width = window
+        .inner_width()
+        .ok()
+        .and_then(|w| w.as_f64())
+        .unwrap_or(f64::MAX);
+    let height = window
+        .inner_height()
+        .ok()
+        .and_then(|h| h.as_f64())
+        .unwrap_or(f64::MAX);
+    let left = (x as f64)
+        .min(width - rect.width() - VIEWPORT_MARGIN_PX)
+        .max(VIEWPORT_MARGIN_PX);
+    let top = (y as f64)
+        .min(height - rect.height() - VIEWPORT_MARGIN_PX)
+        .max(VIEWPORT_MARGIN_PX);
+    (left, top)
+}
+
+fn focused_index(menu: &Element) -> Option<u32> {
+    let active = gloo::utils::document().active_element()?;
+    let items = menu.children();
+    (0..items.length()).find(|&i| items.item(i).as_ref() == Some(&active))
+}
+
+fn focus_item(menu: &Element, index: u32) {
+    if let Some(item) = menu
+        .children()
+        .item(index)
+        .and_then(|e| e.dyn_into::<HtmlElement>().ok())
+    {
+        let _ = item.focus();
+    }
+}
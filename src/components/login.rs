@@ -1,39 +1,168 @@
-use web_sys::HtmlInputElement;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
 use yew::functional::*;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
 use crate::Route;
-use crate::User;
+use crate::{avatar_url, User, UserInner, AVATAR_STYLES};
+
+const USERNAME_MIN_CHARS: usize = 2;
+const USERNAME_MAX_CHARS: usize = 20;
+
+/// Trims `input` and checks it is 2–20 letters, digits or underscores,
+/// returning the trimmed name or a message to show under the field.
+fn validate_username(input: &str) -> Result<String, String> {
+    let name = input.trim();
+    let len = name.chars().count();
+    if len == 0 {
+        Err("Please enter a username.".to_string())
+    } else if !(USERNAME_MIN_CHARS..=USERNAME_MAX_CHARS).contains(&len) {
+        Err(format!(
+            "Usernames must be {}–{} characters long.",
+            USERNAME_MIN_CHARS, USERNAME_MAX_CHARS
+        ))
+    } else if !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        Err("Only letters, digits and underscores are allowed.".to_string())
+    } else {
+        Ok(name.to_string())
+    }
+}
 
 #[function_component(Login)]
 pub fn login() -> Html {
-    let username = use_state(|| String::new());
+    let username = use_state(|| {
+        UserInner::load_from_storage()
+            .map(|saved| saved.username.into_inner())
+            .unwrap_or_default()
+    });
+    let avatar_style = use_state(|| {
+        UserInner::load_from_storage()
+            .map(|saved| saved.avatar_style.into_inner())
+            .unwrap_or_else(|| AVATAR_STYLES[0].to_string())
+    });
     let user = use_context::<User>().expect("No context found.");
+    let server_error = {
+        let user = user.clone();
+        use_state(move || user.login_error.borrow_mut().take())
+    };
 
     let oninput = {
         let current_username = username.clone();
+        let server_error = server_error.clone();
 
         Callback::from(move |e: InputEvent| {
             let input: HtmlInputElement = e.target_unchecked_into();
             current_username.set(input.value());
+            server_error.set(None);
         })
     };
 
+    let onchange = {
+        let avatar_style = avatar_style.clone();
+
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            avatar_style.set(select.value());
+        })
+    };
+
+    let validated = validate_username(&username);
+
     let onclick = {
-        let username = username.clone();
+        let validated = validated.clone();
+        let avatar_style = avatar_style.clone();
         let user = user.clone();
-        Callback::from(move |_| *user.username.borrow_mut() = (*username).clone())
+        Callback::from(move |e: MouseEvent| {
+            let name = match &validated {
+                Ok(name) => name.clone(),
+                Err(_) => {
+                    e.prevent_default();
+                    e.stop_propagation();
+                    return;
+                }
+            };
+            *user.username.borrow_mut() = name;
+            *user.avatar_style.borrow_mut() = (*avatar_style).clone();
+            user.save_to_storage();
+        })
+    };
+
+    let preview_seed = if username.is_empty() {
+        "preview"
+    } else {
+        username.as_str()
     };
 
     html! {
        <div class="bg-gray-800 flex w-screen">
             <div class="container mx-auto flex flex-col justify-center items-center">
                 <form class="m-4 flex">
-                    <input {oninput} class="rounded-l-lg p-4 border-t mr-0 border-b border-l text-gray-800 border-gray-200 bg-white" placeholder="Username" />
-                    <Link<Route> to={Route::Chat}> <button {onclick} disabled={username.len()<1} class="px-8 rounded-r-lg bg-violet-600	  text-white font-bold p-4 uppercase border-violet-600 border-t border-b border-r" >{"Go Chatting!"}</button></Link<Route>>
+                    <img class="w-14 h-14 mr-3 rounded-full bg-white self-center" src={avatar_url(&avatar_style, preview_seed)} alt="avatar preview"/>
+                    <select {onchange} title="Avatar style" class="p-4 mr-3 rounded-lg text-gray-800 bg-white border border-gray-200">
+                        { for AVATAR_STYLES.iter().map(|style| html! {
+                            <option value={*style} selected={*style == avatar_style.as_str()}>{*style}</option>
+                        }) }
+                    </select>
+                    <input {oninput} value={(*username).clone()} class="rounded-l-lg p-4 border-t mr-0 border-b border-l text-gray-800 border-gray-200 bg-white" placeholder="Username" />
+                    <Link<Route> to={Route::Chat}> <button {onclick} disabled={validated.is_err()} class="px-8 rounded-r-lg bg-violet-600	  text-white font-bold p-4 uppercase border-violet-600 border-t border-b border-r" >{"Go Chatting!"}</button></Link<Route>>
                 </form>
+                {
+                    match (&*server_error, &validated) {
+                        (Some(error), _) => html! { <p class="text-sm text-red-400">{error.clone()}</p> },
+                        // Don't nag before anything has been typed.
+                        (None, Err(error)) if !username.is_empty() => html! { <p class="text-sm text-red-400">{error.clone()}</p> },
+                        _ => html! {},
+                    }
+                }
             </div>
         </div>
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_names_are_rejected() {
+        assert_eq!(
+            validate_username(""),
+            Err("Please enter a username.".to_string())
+        );
+        assert_eq!(
+            validate_username("   \t"),
+            Err("Please enter a username.".to_string())
+        );
+    }
+
+    #[test]
+    fn length_limits() {
+        let short = "a".repeat(USERNAME_MIN_CHARS - 1);
+        let long = "a".repeat(USERNAME_MAX_CHARS + 1);
+        assert!(validate_username(&short).is_err());
+        assert!(validate_username(&long).is_err());
+
+        let min = "a".repeat(USERNAME_MIN_CHARS);
+        let max = "a".repeat(USERNAME_MAX_CHARS);
+        assert_eq!(validate_username(&min), Ok(min.clone()));
+        assert_eq!(validate_username(&max), Ok(max.clone()));
+        // Counted in characters, not bytes.
+        let accented = "é".repeat(USERNAME_MAX_CHARS);
+        assert_eq!(validate_username(&accented), Ok(accented.clone()));
+    }
+
+    #[test]
+    fn invalid_characters_are_rejected() {
+        for name in ["bad name", "alice!", "bob@home", "<script>", "a-b"] {
+            assert!(
+                validate_username(name).is_err(),
+                "{:?} should be rejected",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn names_are_trimmed() {
+        assert_eq!(validate_username("  alice_99 "), Ok("alice_99".to_string()));
+    }
+}
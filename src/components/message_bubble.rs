@@ -0,0 +1,182 @@
+//! One chat message: avatar, sender and whatever content the list puts inside.
+
+use serde::{Deserialize, Serialize};
+use yew::functional::*;
+use yew::prelude::*;
+
+use crate::services::websocket::{MessageData, SendStatus};
+
+/// Text longer than this, in characters or lines, starts out collapsed.
+const COLLAPSE_CHARS: usize = 500;
+const COLLAPSE_LINES: usize = 8;
+
+/// How tightly messages are packed in the list.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Density {
+    /// Roomy bubbles with large avatars; the original look.
+    #[default]
+    Cozy,
+    /// Smaller avatars and padding, so more messages fit on screen.
+    Compact,
+}
+
+impl Density {
+    /// Size classes for the avatar next to a bubble.
+    pub fn avatar_size(self) -> &'static str {
+        match self {
+            Density::Cozy => "w-8 h-8",
+            Density::Compact => "w-6 h-6",
+        }
+    }
+
+    fn avatar_width(self) -> &'static str {
+        match self {
+            Density::Cozy => "w-8",
+            Density::Compact => "w-6",
+        }
+    }
+
+    /// Rough height of one message, used to size the windowing spacers.
+    pub fn estimated_row_px(self) -> i32 {
+        match self {
+            Density::Cozy => 96,
+            Density::Compact => 60,
+        }
+    }
+
+    fn spacing(self, grouped: bool) -> &'static str {
+        match (self, grouped) {
+            // Grouped bubbles sit 4px under the previous one instead of the usual gap.
+            (Density::Cozy, true) => "mb-4 -mt-3",
+            (Density::Cozy, false) => "mb-4 mt-4",
+            (Density::Compact, true) => "mb-1 mt-0",
+            (Density::Compact, false) => "mb-1 mt-2",
+        }
+    }
+
+    fn avatar_slot(self) -> &'static str {
+        match self {
+            Density::Cozy => "m-3",
+            Density::Compact => "m-1.5",
+        }
+    }
+
+    fn padding(self, grouped: bool) -> &'static str {
+        match (self, grouped) {
+            (Density::Cozy, true) => "px-3 py-2",
+            (Density::Cozy, false) => "p-3",
+            (Density::Compact, true) => "px-2 py-0.5",
+            (Density::Compact, false) => "px-2 py-1",
+        }
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct MessageBubbleProps {
+    pub message: MessageData,
+    /// Avatar markup, sized by [`Density::avatar_size`]; not shown for grouped messages.
+    pub avatar: Html,
+    /// Background and border colour of the bubble.
+    pub color: String,
+    pub is_own: bool,
+    /// Follows a message from the same sender, so drops the avatar and name.
+    pub grouped: bool,
+    #[prop_or_default]
+    pub density: Density,
+    pub mentions_me: bool,
+    /// Just jumped to from a quote or the pinned list; outlined for a moment.
+    #[prop_or_default]
+    pub highlighted: bool,
+    /// Plays the entrance animation; set only for messages that just arrived.
+    #[prop_or_default]
+    pub animate: bool,
+    /// The sender's name, possibly with search highlighting.
+    pub sender: Html,
+    #[prop_or_default]
+    pub oncontextmenu: Option<Callback<MouseEvent>>,
+    /// The bubble can be focused for keyboard navigation through the list.
+    #[prop_or_default]
+    pub onfocus: Option<Callback<FocusEvent>>,
+    /// The quoted message this one replies to.
+    #[prop_or_default]
+    pub quote: Html,
+    /// The rendered text or image; long text is collapsed behind "Show more".
+    pub body: Html,
+    /// Reactions and footer, under the body.
+    #[prop_or_default]
+    pub children: Children,
+}
+
+#[function_component(MessageBubble)]
+pub fn message_bubble(props: &MessageBubbleProps) -> Html {
+    let m = &props.message;
+    let expanded = use_state(|| false);
+    let long =
+        m.message.chars().count() > COLLAPSE_CHARS || m.message.lines().count() > COLLAPSE_LINES;
+    let toggle = {
+        let expanded = expanded.clone();
+        Callback::from(move |_| expanded.set(!*expanded))
+    };
+    let bubble = if props.is_own {
+        "flex flex-row-reverse items-end max-w-md mx-4 ml-auto rounded-tl-[1rem] rounded-tr-[0.25rem] rounded-bl-[1rem] border"
+    } else {
+        "flex items-end max-w-md mx-4 rounded-tl-[0.25rem] rounded-tr-[1rem] rounded-br-[1rem] border"
+    };
+    let density = props.density;
+
+    html! {
+        <div id={m.id.as_deref().map(message_element_id)} oncontextmenu={props.oncontextmenu.clone()}
+            tabindex="-1" onfocus={props.onfocus.clone()} role="article" aria-label={format!("{}: {}", m.from, m.message)}
+            class={classes!(bubble, density.spacing(props.grouped), "relative", "group", "focus:outline-none", "focus:ring-2", "focus:ring-blue-500", "text-gray-900", "transition-shadow", props.animate.then_some("message-in"),
+                if props.highlighted {
+                    Some("ring-4 ring-yellow-300")
+                } else if props.mentions_me {
+                    Some("ring-2 ring-amber-400")
+                } else {
+                    None
+                },
+                match m.status {
+                    SendStatus::Sending => Some("opacity-60"),
+                    SendStatus::Failed => Some("ring-2 ring-red-500"),
+                    SendStatus::Sent | SendStatus::Delivered => None,
+                })}
+            style={format!("background-color:{}; border-color:{}", props.color, props.color)}>
+            {
+                if props.grouped {
+                    html! { <div class={classes!(density.avatar_width(), density.avatar_slot(), "shrink-0")}></div> }
+                } else {
+                    html! { <div class={classes!(density.avatar_size(), density.avatar_slot(), "shrink-0")}>{props.avatar.clone()}</div> }
+                }
+            }
+            <div class={classes!(density.padding(props.grouped), props.is_own.then_some("text-right"))}>
+                {
+                    if props.grouped {
+                        html! {}
+                    } else {
+                        html! { <div class="text-sm font-semibold">{props.sender.clone()}</div> }
+                    }
+                }
+                {props.quote.clone()}
+                <div class={classes!((long && !*expanded).then_some("max-h-40 overflow-hidden"))}>{props.body.clone()}</div>
+                {
+                    if long {
+                        html! {
+                            <button onclick={toggle} class="text-xs text-blue-600 hover:underline mt-1">
+                                { if *expanded { "Show less" } else { "Show more" } }
+                            </button>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+                { for props.children.iter() }
+            </div>
+        </div>
+    }
+}
+
+/// DOM id of a rendered message, used to scroll replies to their original.
+pub fn message_element_id(id: &str) -> String {
+    format!("msg-{}", id)
+}
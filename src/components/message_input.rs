@@ -0,0 +1,54 @@
+//! The message box with its length counter and send button.
+
+use yew::prelude::*;
+
+use crate::components::theme::Theme;
+
+#[derive(Properties, PartialEq)]
+pub struct MessageInputProps {
+    /// Attached to the `<textarea>`, which the chat reads, edits and resizes directly.
+    pub input_ref: NodeRef,
+    pub theme: Theme,
+    /// Characters in the current draft.
+    pub draft_len: usize,
+    /// Longest message that may be sent; the counter turns red past it.
+    pub max_len: usize,
+    /// Whether the socket is up. While it isn't, the send button is greyed out
+    /// but still works: messages are queued and sent on reconnect.
+    pub connected: bool,
+    pub oninput: Callback<InputEvent>,
+    pub onkeydown: Callback<KeyboardEvent>,
+    /// Sees pastes first, so pasted images can be uploaded instead.
+    pub onpaste: Callback<Event>,
+    pub onsubmit: Callback<MouseEvent>,
+}
+
+#[function_component(MessageInput)]
+pub fn message_input(props: &MessageInputProps) -> Html {
+    let theme = props.theme;
+    let counter_color = if props.draft_len > props.max_len {
+        "text-red-600 font-semibold"
+    } else if props.draft_len * 10 >= props.max_len * 9 {
+        "text-amber-600"
+    } else {
+        theme.muted()
+    };
+
+    html! {
+        <>
+            <textarea ref={props.input_ref.clone()} oninput={props.oninput.clone()} onkeydown={props.onkeydown.clone()} onpaste={props.onpaste.clone()} rows="1" placeholder="Message" aria-label="Message" class={classes!("block", "border", "focus:border-blue-400", "focus:ring-2", "focus:ring-blue-200", "rounded-2xl", "px-4", "py-2", "resize-none", "overflow-y-auto", "transition-colors", "duration-300", "w-full", theme.input())} name="message" required=true></textarea>
+            {
+                if props.draft_len > 0 {
+                    html! { <span class={classes!("ml-3", "mb-2.5", "text-xs", "whitespace-nowrap", counter_color)}>{format!("{}/{}", props.draft_len, props.max_len)}</span> }
+                } else {
+                    html! {}
+                }
+            }
+            <button onclick={props.onsubmit.clone()} title={if props.connected { "Send" } else { "Offline: will send once reconnected" }} aria-label="Send message"
+                class={classes!("ml-3", "transition-transform", "hover:scale-110", "active:translate-x-1", "text-white", "p-2", "rounded-full",
+                    if props.connected { "bg-pink-500 hover:bg-pink-600" } else { "bg-gray-400 hover:bg-gray-500 opacity-70" })}>
+                <svg class="w-5 h-5 fill-current" viewBox="0 0 24 24" aria-hidden="true"><path d="M2.01 21L23 12 2.01 3 2 10l15 2-15 2z"/></svg>
+            </button>
+        </>
+    }
+}
@@ -1,2 +1,10 @@
 pub mod chat;
-pub mod login;
\ No newline at end of file
+pub mod context_menu;
+pub mod login;
+pub mod message_bubble;
+pub mod message_input;
+pub mod profile_card;
+pub mod settings;
+pub mod theme;
+pub mod toast;
+pub mod user_card;
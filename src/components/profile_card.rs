@@ -0,0 +1,71 @@
+//! A popover with a user's avatar, name and status, shown on hover.
+
+use web_sys::Element;
+use yew::functional::*;
+use yew::prelude::*;
+
+use crate::components::context_menu::fit_in_viewport;
+
+#[derive(Properties, PartialEq)]
+pub struct ProfileCardProps {
+    pub name: String,
+    /// Avatar markup, sized `w-16 h-16`.
+    pub avatar: Html,
+    pub color: String,
+    /// "Online", "Away" or similar.
+    pub status: String,
+    /// Where to open, in viewport pixels; moved inward to stay on screen.
+    pub x: i32,
+    pub y: i32,
+    /// Starts a direct conversation; `None` hides the button, e.g. on our own card.
+    #[prop_or_default]
+    pub on_message: Option<Callback<()>>,
+    /// Lets the owner keep the card open while the pointer is over it.
+    pub onmouseenter: Callback<MouseEvent>,
+    pub onmouseleave: Callback<MouseEvent>,
+}
+
+#[function_component(ProfileCard)]
+pub fn profile_card(props: &ProfileCardProps) -> Html {
+    let card = use_node_ref();
+    let position = use_state(|| (props.x as f64, props.y as f64));
+
+    {
+        let card = card.clone();
+        let position = position.clone();
+        use_effect_with_deps(
+            move |&(x, y)| {
+                if let Some(element) = card.cast::<Element>() {
+                    position.set(fit_in_viewport(&element, x, y));
+                }
+                || ()
+            },
+            (props.x, props.y),
+        );
+    }
+
+    let (left, top) = *position;
+    html! {
+        <div ref={card} onmouseenter={props.onmouseenter.clone()} onmouseleave={props.onmouseleave.clone()}
+            style={format!("left:{}px; top:{}px", left, top)}
+            class="fixed z-40 w-56 p-3 rounded-lg shadow-lg border bg-white border-gray-200 text-gray-800">
+            <div class="flex items-center gap-3">
+                <div class="w-16 h-16 flex-none rounded-full" style={format!("background-color:{}", props.color)}>{props.avatar.clone()}</div>
+                <div class="min-w-0">
+                    <div class="font-semibold truncate">{props.name.clone()}</div>
+                    <div class="text-xs text-gray-500">{props.status.clone()}</div>
+                </div>
+            </div>
+            {
+                match &props.on_message {
+                    Some(on_message) => html! {
+                        <button onclick={on_message.reform(|_: MouseEvent| ())} class="mt-3 w-full text-sm font-semibold py-1 rounded-full bg-pink-500 hover:bg-pink-600 text-white">
+                            {"Message"}
+                        </button>
+                    },
+                    None => html! {},
+                }
+            }
+        </div>
+    }
+}
@@ -0,0 +1,214 @@
+//! User preferences, saved together in localStorage, and the drawer that edits them.
+
+use gloo::events::EventListener;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsCast;
+use yew::functional::*;
+use yew::prelude::*;
+
+use crate::components::message_bubble::Density;
+use crate::components::theme::{FontSize, Palette, Theme};
+use crate::services::storage::{self, StorageError, Stored};
+
+/// Where settings were kept before the storage layer.
+const LEGACY_SETTINGS_KEY: &str = "yewchat.settings";
+
+/// Display and notification preferences. The avatar style isn't here: it is
+/// part of who the user is, picked at login and sent to the server.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub theme: Theme,
+    pub font_size: FontSize,
+    pub density: Density,
+    pub palette: Palette,
+    /// 12-hour times with AM/PM instead of 24-hour ones.
+    pub hour12: bool,
+    pub sound_muted: bool,
+    /// Render `**bold**`, `*italic*` and `` `code` `` in messages.
+    pub markdown: bool,
+    /// Swear words in messages are shown as asterisks.
+    pub mask_profanity: bool,
+    /// Letter headings between users in the sidebar.
+    pub group_users: bool,
+    /// No entrance animation for new messages.
+    pub reduce_motion: bool,
+}
+
+impl Default for Settings {
+    /// Follows the system theme, motion preference and the locale's clock.
+    fn default() -> Self {
+        Self {
+            theme: Theme::system(),
+            font_size: FontSize::default(),
+            density: Density::default(),
+            palette: Palette::default(),
+            hour12: locale_prefers_hour12(),
+            sound_muted: false,
+            markdown: false,
+            mask_profanity: false,
+            group_users: false,
+            reduce_motion: prefers_reduced_motion(),
+        }
+    }
+}
+
+impl Stored for Settings {
+    const NAME: &'static str = "settings";
+    const VERSION: u32 = 1;
+
+    fn legacy(storage: &web_sys::Storage) -> Option<Self> {
+        storage::take_legacy(storage, LEGACY_SETTINGS_KEY)
+            .and_then(|json| serde_json::from_str(&json).ok())
+    }
+}
+
+impl Settings {
+    /// The saved settings; missing or unreadable ones fall back to defaults.
+    pub fn load() -> Self {
+        storage::get()
+    }
+
+    pub fn save(&self) -> Result<(), StorageError> {
+        storage::set(self)
+    }
+}
+
+fn prefers_reduced_motion() -> bool {
+    web_sys::window()
+        .and_then(|w| {
+            w.match_media("(prefers-reduced-motion: reduce)")
+                .ok()
+                .flatten()
+        })
+        .is_some_and(|query| query.matches())
+}
+
+/// Whether the browser's locale uses a 12-hour clock.
+fn locale_prefers_hour12() -> bool {
+    let options = js_sys::Object::new();
+    let _ = js_sys::Reflect::set(&options, &"hour".into(), &"numeric".into());
+    let resolved =
+        js_sys::Intl::DateTimeFormat::new(&js_sys::Array::new(), &options).resolved_options();
+    js_sys::Reflect::get(&resolved, &"hour12".into())
+        .ok()
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+#[derive(Properties, PartialEq)]
+pub struct SettingsPanelProps {
+    pub settings: Settings,
+    /// Called with the whole updated settings after every change.
+    pub on_change: Callback<Settings>,
+    /// Called from the close button, a click outside or Esc.
+    pub on_close: Callback<()>,
+}
+
+/// A drawer on the right edge of the screen.
+#[function_component(SettingsPanel)]
+pub fn settings_panel(props: &SettingsPanelProps) -> Html {
+    {
+        let on_close = props.on_close.clone();
+        use_effect_with_deps(
+            move |_| {
+                let escape = EventListener::new(&gloo::utils::document(), "keydown", move |e| {
+                    if e.dyn_ref::<KeyboardEvent>()
+                        .is_some_and(|k| k.key() == "Escape")
+                    {
+                        on_close.emit(());
+                    }
+                });
+                move || drop(escape)
+            },
+            (),
+        );
+    }
+
+    let settings = props.settings;
+    let theme = settings.theme;
+    let update = |change: fn(&mut Settings)| {
+        let on_change = props.on_change.clone();
+        Callback::from(move |()| {
+            let mut updated = settings;
+            change(&mut updated);
+            on_change.emit(updated);
+        })
+    };
+    let toggle = |label: &str, checked: bool, change: fn(&mut Settings)| {
+        html! {
+            <label class="flex items-center gap-2 py-1 cursor-pointer">
+                <input type="checkbox" {checked} onchange={update(change).reform(|_: Event| ())} />
+                {label.to_string()}
+            </label>
+        }
+    };
+    let heading = |text: &str| {
+        html! {
+            <div class={classes!("mt-4", "mb-1", "text-xs", "font-semibold", "uppercase", theme.muted())}>{text.to_string()}</div>
+        }
+    };
+    let choice = |label: &str, active: bool, on_pick: Callback<()>| {
+        html! {
+            <button onclick={on_pick.reform(|_: MouseEvent| ())} class={classes!("grow", "px-2", "py-0.5", "rounded-full", "transition-colors", theme.toolbar_button(active))}>
+                {label.to_string()}
+            </button>
+        }
+    };
+
+    html! {
+        <div class="fixed inset-0 z-40 flex justify-end">
+            <div class="absolute inset-0 bg-black/30" onclick={props.on_close.reform(|_: MouseEvent| ())}></div>
+            <div role="dialog" aria-label="Settings"
+                class={classes!("relative", "w-72", "max-w-full", "h-full", "overflow-y-auto", "p-4", "shadow-xl", "text-sm", theme.page())}>
+                <div class="flex items-center justify-between">
+                    <div class="text-lg font-semibold">{"Settings"}</div>
+                    <button onclick={props.on_close.reform(|_: MouseEvent| ())} title="Close" class={classes!("px-2", "rounded-full", theme.toolbar_button(false))}>{"✕"}</button>
+                </div>
+
+                {heading("Appearance")}
+                <div class="flex gap-1">
+                    {choice("Light", theme == Theme::Light, update(|s| s.theme = Theme::Light))}
+                    {choice("Dark", theme == Theme::Dark, update(|s| s.theme = Theme::Dark))}
+                </div>
+
+                {heading("User colours")}
+                <div class="flex gap-1">
+                    {
+                        Palette::ALL.iter().map(|&palette| {
+                            let on_change = props.on_change.clone();
+                            let on_pick = Callback::from(move |()| on_change.emit(Settings { palette, ..settings }));
+                            choice(palette.label(), settings.palette == palette, on_pick)
+                        }).collect::<Html>()
+                    }
+                </div>
+
+                {heading("Text size")}
+                <div class="flex gap-1">
+                    {
+                        FontSize::ALL.iter().map(|&size| {
+                            let on_change = props.on_change.clone();
+                            let on_pick = Callback::from(move |()| on_change.emit(Settings { font_size: size, ..settings }));
+                            choice(size.label(), settings.font_size == size, on_pick)
+                        }).collect::<Html>()
+                    }
+                </div>
+
+                {heading("Messages")}
+                {toggle("Compact messages", settings.density == Density::Compact, |s| {
+                    s.density = if s.density == Density::Compact { Density::Cozy } else { Density::Compact };
+                })}
+                {toggle("Reduce motion", settings.reduce_motion, |s| s.reduce_motion = !s.reduce_motion)}
+                {toggle("12-hour clock", settings.hour12, |s| s.hour12 = !s.hour12)}
+                {toggle("Markdown formatting", settings.markdown, |s| s.markdown = !s.markdown)}
+                {toggle("Hide swear words", settings.mask_profanity, |s| s.mask_profanity = !s.mask_profanity)}
+
+                {heading("Sidebar")}
+                {toggle("Group people by first letter", settings.group_users, |s| s.group_users = !s.group_users)}
+
+                {heading("Notifications")}
+                {toggle("Play a sound for new messages", !settings.sound_muted, |s| s.sound_muted = !s.sound_muted)}
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,265 @@
+//! Light and dark palettes for the chat UI, as Tailwind class strings, and
+//! the colours that tell users apart.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+impl Theme {
+    /// The system's `prefers-color-scheme`, for when the user hasn't picked one.
+    pub fn system() -> Self {
+        if prefers_dark() {
+            Theme::Dark
+        } else {
+            Theme::Light
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            Theme::Light => Theme::Dark,
+            Theme::Dark => Theme::Light,
+        }
+    }
+
+    pub fn page(self) -> &'static str {
+        match self {
+            Theme::Light => "bg-gradient-to-br from-blue-50 to-pink-50 text-gray-900",
+            Theme::Dark => "bg-gray-900 text-gray-100",
+        }
+    }
+
+    pub fn sidebar(self) -> &'static str {
+        match self {
+            Theme::Light => "bg-amber-25",
+            Theme::Dark => "bg-gray-800",
+        }
+    }
+
+    pub fn sidebar_header(self) -> &'static str {
+        match self {
+            Theme::Light => "bg-amber-200 border-amber-300",
+            Theme::Dark => "bg-gray-700 border-gray-600",
+        }
+    }
+
+    /// Small pills in the sidebar header, e.g. the user count and log out.
+    pub fn chip(self) -> &'static str {
+        match self {
+            Theme::Light => "bg-amber-300 hover:bg-amber-400",
+            Theme::Dark => "bg-gray-600 hover:bg-gray-500",
+        }
+    }
+
+    pub fn room(self, active: bool) -> &'static str {
+        match (self, active) {
+            (Theme::Light, true) => "bg-amber-200 font-semibold",
+            (Theme::Light, false) => "hover:bg-amber-100",
+            (Theme::Dark, true) => "bg-gray-700 font-semibold",
+            (Theme::Dark, false) => "hover:bg-gray-700",
+        }
+    }
+
+    pub fn card_border(self) -> &'static str {
+        match self {
+            Theme::Light => "border-amber-300",
+            Theme::Dark => "border-gray-600",
+        }
+    }
+
+    pub fn muted(self) -> &'static str {
+        match self {
+            Theme::Light => "text-gray-500",
+            Theme::Dark => "text-gray-400",
+        }
+    }
+
+    pub fn header(self) -> &'static str {
+        match self {
+            Theme::Light => "bg-pink-200 border-pink-300",
+            Theme::Dark => "bg-gray-800 border-gray-700",
+        }
+    }
+
+    /// Header toggle buttons; `active` marks a setting that is switched on.
+    pub fn toolbar_button(self, active: bool) -> &'static str {
+        match (self, active) {
+            (Theme::Light, true) => "bg-pink-500 text-white",
+            (Theme::Light, false) => "hover:bg-pink-300",
+            (Theme::Dark, true) => "bg-pink-600 text-white",
+            (Theme::Dark, false) => "hover:bg-gray-700",
+        }
+    }
+
+    /// The message list and the strips attached to it (search, typing).
+    pub fn panel(self) -> &'static str {
+        match self {
+            Theme::Light => "bg-pink-50 border-pink-300",
+            Theme::Dark => "bg-gray-900 border-gray-700",
+        }
+    }
+
+    pub fn divider(self) -> &'static str {
+        match self {
+            Theme::Light => "border-pink-200",
+            Theme::Dark => "border-gray-700",
+        }
+    }
+
+    pub fn input(self) -> &'static str {
+        match self {
+            Theme::Light => "bg-white text-gray-700 border-pink-300 placeholder-gray-500",
+            Theme::Dark => "bg-gray-700 text-gray-100 border-gray-600 placeholder-gray-400",
+        }
+    }
+
+    pub fn input_bar(self) -> &'static str {
+        match self {
+            Theme::Light => "bg-pink-200 border-pink-300",
+            Theme::Dark => "bg-gray-800 border-gray-700",
+        }
+    }
+}
+
+/// Base text size, set on `<html>` so everything sized in `rem` scales with it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FontSize {
+    Small,
+    #[default]
+    Medium,
+    Large,
+}
+
+impl FontSize {
+    pub const ALL: [FontSize; 3] = [FontSize::Small, FontSize::Medium, FontSize::Large];
+
+    /// Swaps the size class on the root element.
+    pub fn apply(self) {
+        let Some(root) = gloo::utils::document().document_element() else {
+            return;
+        };
+        let classes = root.class_list();
+        for size in Self::ALL {
+            let _ = classes.remove_1(size.root_class());
+        }
+        let _ = classes.add_1(self.root_class());
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FontSize::Small => "Small",
+            FontSize::Medium => "Medium",
+            FontSize::Large => "Large",
+        }
+    }
+
+    fn root_class(self) -> &'static str {
+        match self {
+            FontSize::Small => "text-[14px]",
+            FontSize::Medium => "text-[16px]",
+            FontSize::Large => "text-[19px]",
+        }
+    }
+}
+
+/// Background colours given to users, picked by hashing their name.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Palette {
+    #[default]
+    Pastel,
+    /// Stronger colours that are easier to tell apart.
+    HighContrast,
+}
+
+const PASTEL: [&str; 10] = [
+    "#fce4ec", "#e3f2fd", "#f3e5f5", "#e8f5e9", "#fff8e1", "#fbe9e7", "#ede7f6", "#e0f7fa",
+    "#f9fbe7", "#f1f8e9",
+];
+
+/// Still light enough for dark text on top.
+const HIGH_CONTRAST: [&str; 10] = [
+    "#f48fb1", "#81d4fa", "#ce93d8", "#a5d6a7", "#ffd54f", "#ffab91", "#b39ddb", "#80deea",
+    "#e6ee9c", "#bcaaa4",
+];
+
+impl Palette {
+    pub const ALL: [Palette; 2] = [Palette::Pastel, Palette::HighContrast];
+
+    pub fn colors(self) -> &'static [&'static str] {
+        match self {
+            Palette::Pastel => &PASTEL,
+            Palette::HighContrast => &HIGH_CONTRAST,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Palette::Pastel => "Pastel",
+            Palette::HighContrast => "High contrast",
+        }
+    }
+}
+
+/// The colour for `name` from `palette`; a name always gets the same entry,
+/// wherever it is shown.
+pub fn color_for(name: &str, palette: &[&str]) -> String {
+    palette[(fnv1a(name) % palette.len() as u64) as usize].to_string()
+}
+
+/// 64-bit FNV-1a, so a username always lands on the same palette entry.
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn prefers_dark() -> bool {
+    web_sys::window()
+        .and_then(|w| w.match_media("(prefers-color-scheme: dark)").ok().flatten())
+        .is_some_and(|query| query.matches())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 5] = ["alice", "bob", "carol", "dave", "erin"];
+
+    #[test]
+    fn colors_are_stable_and_from_the_palette() {
+        for palette in Palette::ALL {
+            for name in NAMES {
+                let color = color_for(name, palette.colors());
+                assert_eq!(color, color_for(name, palette.colors()));
+                assert!(palette.colors().contains(&color.as_str()));
+            }
+        }
+        // Pinned, so a change to the hash shows up as recolouring everyone.
+        assert_eq!(fnv1a(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a("a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn colors_ignore_user_list_order() {
+        let colors = |names: &[&str]| -> Vec<(String, String)> {
+            let mut colors: Vec<_> = names
+                .iter()
+                .map(|n| (n.to_string(), color_for(n, Palette::Pastel.colors())))
+                .collect();
+            colors.sort();
+            colors
+        };
+        let mut reversed = NAMES;
+        reversed.reverse();
+
+        assert_eq!(colors(&NAMES), colors(&reversed));
+        assert_eq!(colors(&NAMES[..2]), colors(&["bob", "alice"]));
+    }
+}
@@ -0,0 +1,46 @@
+//! Short dismissible notices stacked in a corner of the screen.
+
+use yew::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToastKind {
+    /// Something went wrong, e.g. the server rejected a request.
+    Error,
+    /// A problem with what the user just did, e.g. sending too fast.
+    Warning,
+}
+
+impl ToastKind {
+    fn classes(self) -> &'static str {
+        match self {
+            ToastKind::Error => "bg-red-600 text-white",
+            ToastKind::Warning => "bg-amber-400 text-gray-900",
+        }
+    }
+
+    fn icon(self) -> &'static str {
+        match self {
+            ToastKind::Error => "⛔",
+            ToastKind::Warning => "⚠",
+        }
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ToastProps {
+    pub kind: ToastKind,
+    pub message: String,
+    pub on_dismiss: Callback<()>,
+}
+
+#[function_component(Toast)]
+pub fn toast(props: &ToastProps) -> Html {
+    let onclick = props.on_dismiss.reform(|_: MouseEvent| ());
+    html! {
+        <div role="alert" class={classes!("flex", "items-start", "gap-2", "max-w-sm", "px-3", "py-2", "rounded-lg", "shadow-lg", "text-sm", props.kind.classes())}>
+            <span>{props.kind.icon()}</span>
+            <span class="grow">{props.message.clone()}</span>
+            <button {onclick} title="Dismiss" aria-label="Dismiss" class="leading-none opacity-75 hover:opacity-100">{"✕"}</button>
+        </div>
+    }
+}
@@ -0,0 +1,114 @@
+//! A user in the sidebar list, which opens a direct conversation when clicked.
+
+use yew::prelude::*;
+
+use crate::components::context_menu::{ContextMenu, MenuItem};
+use crate::components::theme::Theme;
+
+#[derive(Properties, PartialEq)]
+pub struct UserCardProps {
+    pub name: String,
+    /// Avatar markup, sized `w-12 h-12`.
+    pub avatar: Html,
+    pub color: String,
+    pub online: bool,
+    /// Idle for a while; the avatar is dimmed.
+    pub away: bool,
+    /// This user's direct conversation is on screen.
+    pub active: bool,
+    pub muted: bool,
+    /// Unread direct messages from this user.
+    pub unread: usize,
+    /// The current user's own card, which can't be muted.
+    pub is_self: bool,
+    pub theme: Theme,
+    pub on_open: Callback<()>,
+    /// From the mute button or the card's right-click menu.
+    pub on_toggle_mute: Callback<()>,
+    #[prop_or_default]
+    pub onmouseenter: Callback<MouseEvent>,
+    #[prop_or_default]
+    pub onmouseleave: Callback<MouseEvent>,
+}
+
+#[function_component(UserCard)]
+pub fn user_card(props: &UserCardProps) -> Html {
+    let onclick = props.on_open.reform(|_: MouseEvent| ());
+    let menu = use_state(|| None::<(i32, i32)>);
+    let oncontextmenu = {
+        let menu = menu.clone();
+        Callback::from(move |e: MouseEvent| {
+            e.prevent_default();
+            menu.set(Some((e.client_x(), e.client_y())));
+        })
+    };
+    let toggle_mute = props
+        .on_toggle_mute
+        .reform(|e: MouseEvent| e.stop_propagation());
+    let muted = props.muted;
+
+    let context_menu = match *menu {
+        Some((x, y)) => {
+            let mut items = vec![MenuItem::new("💬 Message", props.on_open.clone())];
+            if !props.is_self {
+                items.push(MenuItem::new(
+                    if muted { "🔈 Unmute" } else { "🔇 Mute" },
+                    props.on_toggle_mute.clone(),
+                ));
+            }
+            let on_close = Callback::from(move |_| menu.set(None));
+            html! { <ContextMenu {x} {y} {items} {on_close} /> }
+        }
+        None => html! {},
+    };
+
+    html! {
+        <>
+        <div {onclick} {oncontextmenu} onmouseenter={props.onmouseenter.clone()} onmouseleave={props.onmouseleave.clone()} class={classes!("group", "flex", "m-3", muted.then_some("opacity-60"), "rounded-lg", "p-2", "border-1", "text-gray-900", "cursor-pointer", props.theme.card_border(), props.active.then_some("ring-2 ring-pink-500"))} style={format!("background-color:{}", props.color)}>
+            <div class="relative flex-none">
+                <div class={classes!("w-12", "h-12", "hover:scale-110", "hover:brightness-125", "transition-transform", "duration-300", props.away.then_some("opacity-50 grayscale"))}>
+                    {props.avatar.clone()}
+                </div>
+                <span class={classes!("absolute", "bottom-0", "right-0", "w-3", "h-3", "rounded-full", "border-2", "border-white",
+                    match (props.online, props.away) {
+                        (false, _) => "bg-gray-400",
+                        (true, true) => "bg-yellow-400",
+                        (true, false) => "bg-green-500",
+                    })}
+                    title={match (props.online, props.away) {
+                        (false, _) => "Offline",
+                        (true, true) => "Away",
+                        (true, false) => "Online",
+                    }}></span>
+            </div>
+            <div class="flex-grow p-3">
+                <div class="flex text-xs justify-between">
+                    <div class="font-semibold">{props.name.clone()}</div>
+                    {
+                        if props.is_self {
+                            html! {}
+                        } else {
+                            html! {
+                                <button onclick={toggle_mute} title={if muted { "Unmute" } else { "Mute (hide their messages)" }}
+                                    aria-label={if muted { format!("Unmute {}", props.name) } else { format!("Mute {}", props.name) }}
+                                    class={classes!("hover:scale-110", (!muted).then_some("hidden group-hover:inline"))}>
+                                    { if muted { "🔇" } else { "🔈" } }
+                                </button>
+                            }
+                        }
+                    }
+                    {
+                        if props.unread > 0 {
+                            html! { <span class="px-1.5 rounded-full bg-pink-500 text-white font-semibold" title="Unread direct messages">{props.unread}</span> }
+                        } else {
+                            html! {}
+                        }
+                    }
+                </div>
+                <div class="text-xs text-gray-600">{"Hi there!"}</div>
+            </div>
+        </div>
+        {context_menu}
+        </>
+    }
+}
@@ -1,4 +1,6 @@
 #![recursion_limit = "512"]
+// yew 0.19's `html!` expands props and children into code these lints trip on.
+#![allow(clippy::unnecessary_operation, clippy::let_unit_value)]
 
 mod components;
 mod services;
@@ -6,6 +8,7 @@ mod services;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 use yew::functional::*;
 use yew::prelude::*;
@@ -13,6 +16,7 @@ use yew_router::prelude::*;
 
 use components::chat::Chat;
 use components::login::Login;
+use services::storage::{self, Stored};
 
 // When the `wee_alloc` feature is enabled, this uses `wee_alloc` as the global
 // allocator.
@@ -38,15 +42,114 @@ pub type User = Rc<UserInner>;
 #[derive(Debug, PartialEq)]
 pub struct UserInner {
     pub username: RefCell<String>,
+    /// One of `AVATAR_STYLES`.
+    pub avatar_style: RefCell<String>,
+    /// Why the server sent us back to the login screen, shown there once.
+    pub login_error: RefCell<Option<String>>,
+}
+
+/// Where the login was kept before the storage layer, one key per field.
+const LEGACY_USERNAME_KEY: &str = "username";
+const LEGACY_AVATAR_STYLE_KEY: &str = "avatarStyle";
+
+/// The last login, offered again on the next visit.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct SavedLogin {
+    username: String,
+    avatar_style: String,
+}
+
+impl Stored for SavedLogin {
+    const NAME: &'static str = "login";
+    const VERSION: u32 = 1;
+
+    fn legacy(storage: &web_sys::Storage) -> Option<Self> {
+        let username = storage::take_legacy(storage, LEGACY_USERNAME_KEY)?;
+        let avatar_style =
+            storage::take_legacy(storage, LEGACY_AVATAR_STYLE_KEY).unwrap_or_default();
+        Some(Self {
+            username,
+            avatar_style,
+        })
+    }
+}
+
+/// Dicebear styles users can pick from; the first is the default.
+pub const AVATAR_STYLES: [&str; 4] = ["pixel-art", "bottts", "identicon", "avataaars"];
+
+/// Dicebear avatar for `seed`, falling back to the default style for unknown ones.
+pub fn avatar_url(style: &str, seed: &str) -> String {
+    let style = if AVATAR_STYLES.contains(&style) {
+        style
+    } else {
+        AVATAR_STYLES[0]
+    };
+    format!("https://api.dicebear.com/9.x/{}/svg?seed={}", style, seed)
+}
+
+impl UserInner {
+    /// Nobody logged in yet; the chat sends these to the login screen.
+    pub fn signed_out() -> Self {
+        Self {
+            username: RefCell::new(String::new()),
+            avatar_style: RefCell::new(AVATAR_STYLES[0].into()),
+            login_error: RefCell::new(None),
+        }
+    }
+
+    pub fn is_signed_in(&self) -> bool {
+        !self.username.borrow().is_empty()
+    }
+
+    /// Restores the last used username and avatar style, if localStorage is
+    /// available and has a username.
+    pub fn load_from_storage() -> Option<Self> {
+        let SavedLogin {
+            username,
+            avatar_style,
+        } = storage::get();
+        if username.is_empty() {
+            return None;
+        }
+        let avatar_style = if AVATAR_STYLES.contains(&avatar_style.as_str()) {
+            avatar_style
+        } else {
+            AVATAR_STYLES[0].to_string()
+        };
+        Some(Self {
+            username: RefCell::new(username),
+            avatar_style: RefCell::new(avatar_style),
+            login_error: RefCell::new(None),
+        })
+    }
+
+    /// Remembers the current username and avatar style; silently does nothing
+    /// without storage.
+    pub fn save_to_storage(&self) {
+        let _ = storage::set(&SavedLogin {
+            username: self.username.borrow().clone(),
+            avatar_style: self.avatar_style.borrow().clone(),
+        });
+    }
+
+    /// Signs out: clears the username and forgets the remembered login, so
+    /// reloading shows the login screen again.
+    pub fn sign_out(&self) {
+        self.username.borrow_mut().clear();
+        storage::remove::<SavedLogin>();
+    }
+}
+
+/// `None` when storage is disabled, e.g. in some private browsing modes.
+pub(crate) fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
 }
 
 #[function_component(Main)]
 fn main() -> Html {
-    let ctx = use_state(|| {
-        Rc::new(UserInner {
-            username: RefCell::new("initial".into()),
-        })
-    });
+    let ctx =
+        use_state(|| Rc::new(UserInner::load_from_storage().unwrap_or_else(UserInner::signed_out)));
 
     html! {
         <ContextProvider<User> context={(*ctx).clone()}>
@@ -62,14 +165,24 @@ fn main() -> Html {
 fn switch(selected_route: &Route) -> Html {
     match selected_route {
         Route::Login => html! {<Login />},
-        Route::Chat => html! {<Chat/>},
+        Route::Chat => html! {<ChatRoute/>},
         Route::NotFound => html! {<h1>{"404 baby"}</h1>},
     }
 }
 
+/// The chat, or the login screen for anyone arriving without a username,
+/// e.g. by opening `/chat` directly.
+#[function_component(ChatRoute)]
+fn chat_route() -> Html {
+    match use_context::<User>() {
+        Some(user) if user.is_signed_in() => html! {<Chat/>},
+        _ => html! {<Redirect<Route> to={Route::Login}/>},
+    }
+}
+
 #[wasm_bindgen]
 pub fn run_app() -> Result<(), JsValue> {
     wasm_logger::init(wasm_logger::Config::default());
     yew::start_app::<Main>();
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,39 @@
+//! Copying text via the async Clipboard API, with a selection fallback.
+//!
+//! `navigator.clipboard` is still behind `web_sys_unstable_apis`, so it is
+//! reached through `Reflect` instead.
+
+use js_sys::{Function, Promise, Reflect};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+/// Writes `text` to the clipboard. Fails when the API is missing (e.g. on
+/// plain http) or the user denied permission.
+pub async fn write_text(text: &str) -> Result<(), JsValue> {
+    let navigator = web_sys::window().ok_or("no window")?.navigator();
+    let clipboard = Reflect::get(&navigator, &"clipboard".into())?;
+    if clipboard.is_undefined() {
+        return Err("clipboard API unavailable".into());
+    }
+    let write: Function = Reflect::get(&clipboard, &"writeText".into())?.dyn_into()?;
+    let promise: Promise = write.call1(&clipboard, &text.into())?.dyn_into()?;
+    JsFuture::from(promise).await.map(|_| ())
+}
+
+/// Selects the contents of the element with `id`, so the user can copy it
+/// with the keyboard when `write_text` isn't allowed.
+pub fn select_element(id: &str) {
+    let document = gloo::utils::document();
+    let (Some(element), Some(selection)) = (
+        document.get_element_by_id(id),
+        web_sys::window().and_then(|w| w.get_selection().ok().flatten()),
+    ) else {
+        return;
+    };
+    if let Ok(range) = document.create_range() {
+        if range.select_node_contents(&element).is_ok() {
+            let _ = selection.remove_all_ranges();
+            let _ = selection.add_range(&range);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Client-side slash commands typed into the message box.
+
+pub const SHRUG: &str = r"¯\_(ツ)_/¯";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// `/me dances`: an action, shown as "* Alice dances".
+    Me(String),
+    /// `/shrug [text]`: the text followed by `¯\_(ツ)_/¯`.
+    Shrug(String),
+    /// Anything else starting with `/`, holding the command name.
+    Unknown(String),
+}
+
+impl Command {
+    /// The help line shown when a command is typed incorrectly.
+    pub fn usage() -> &'static str {
+        "Commands: /me <action>, /shrug [text]"
+    }
+}
+
+/// Parses `input` as a slash command. Plain messages return `None`; so does a
+/// leading `//`, which sends the rest starting with a single `/`.
+pub fn parse_command(input: &str) -> Option<Command> {
+    let body = input.strip_prefix('/')?;
+    if body.starts_with('/') {
+        return None;
+    }
+
+    let (name, args) = body.split_once(char::is_whitespace).unwrap_or((body, ""));
+    let args = args.trim().to_string();
+    Some(match name {
+        "me" => Command::Me(args),
+        "shrug" => Command::Shrug(args),
+        _ => Command::Unknown(name.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_commands() {
+        assert_eq!(
+            parse_command("/me dances"),
+            Some(Command::Me("dances".to_string()))
+        );
+        assert_eq!(
+            parse_command("/me  waves  hello "),
+            Some(Command::Me("waves  hello".to_string()))
+        );
+        assert_eq!(
+            parse_command("/shrug oh well"),
+            Some(Command::Shrug("oh well".to_string()))
+        );
+    }
+
+    #[test]
+    fn missing_arguments_are_empty() {
+        assert_eq!(parse_command("/me"), Some(Command::Me(String::new())));
+        assert_eq!(parse_command("/me   "), Some(Command::Me(String::new())));
+        assert_eq!(parse_command("/shrug"), Some(Command::Shrug(String::new())));
+    }
+
+    #[test]
+    fn unknown_commands() {
+        assert_eq!(
+            parse_command("/dance now"),
+            Some(Command::Unknown("dance".to_string()))
+        );
+        // Names are case-sensitive.
+        assert_eq!(
+            parse_command("/ME waves"),
+            Some(Command::Unknown("ME".to_string()))
+        );
+        assert_eq!(parse_command("/"), Some(Command::Unknown(String::new())));
+    }
+
+    #[test]
+    fn plain_text_is_not_a_command() {
+        assert_eq!(parse_command("hello"), None);
+        assert_eq!(parse_command(" /me dances"), None);
+        assert_eq!(parse_command("and/or"), None);
+        assert_eq!(parse_command("//me is how you'd write it"), None);
+    }
+}
@@ -0,0 +1,177 @@
+//! `:shortcode:` to Unicode emoji replacement for displayed messages.
+
+const SHORTCODES: &[(&str, &str)] = &[
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("grin", "😁"),
+    ("grinning", "😀"),
+    ("laughing", "😆"),
+    ("joy", "😂"),
+    ("rofl", "🤣"),
+    ("sweat_smile", "😅"),
+    ("blush", "😊"),
+    ("innocent", "😇"),
+    ("wink", "😉"),
+    ("heart_eyes", "😍"),
+    ("kissing_heart", "😘"),
+    ("yum", "😋"),
+    ("stuck_out_tongue", "😛"),
+    ("sunglasses", "😎"),
+    ("nerd", "🤓"),
+    ("thinking", "🤔"),
+    ("neutral_face", "😐"),
+    ("expressionless", "😑"),
+    ("unamused", "😒"),
+    ("roll_eyes", "🙄"),
+    ("smirk", "😏"),
+    ("relieved", "😌"),
+    ("pensive", "😔"),
+    ("sleepy", "😪"),
+    ("sleeping", "😴"),
+    ("mask", "😷"),
+    ("confused", "😕"),
+    ("worried", "😟"),
+    ("cry", "😢"),
+    ("sob", "😭"),
+    ("scream", "😱"),
+    ("angry", "😠"),
+    ("rage", "😡"),
+    ("skull", "💀"),
+    ("poop", "💩"),
+    ("clown", "🤡"),
+    ("ghost", "👻"),
+    ("alien", "👽"),
+    ("robot", "🤖"),
+    ("cat", "🐱"),
+    ("dog", "🐶"),
+    ("heart", "❤️"),
+    ("broken_heart", "💔"),
+    ("sparkling_heart", "💖"),
+    ("fire", "🔥"),
+    ("star", "⭐"),
+    ("sparkles", "✨"),
+    ("zap", "⚡"),
+    ("boom", "💥"),
+    ("100", "💯"),
+    ("thumbsup", "👍"),
+    ("+1", "👍"),
+    ("thumbsdown", "👎"),
+    ("-1", "👎"),
+    ("ok_hand", "👌"),
+    ("clap", "👏"),
+    ("wave", "👋"),
+    ("pray", "🙏"),
+    ("muscle", "💪"),
+    ("eyes", "👀"),
+    ("tada", "🎉"),
+    ("party", "🥳"),
+    ("gift", "🎁"),
+    ("cake", "🍰"),
+    ("coffee", "☕"),
+    ("tea", "🍵"),
+    ("pizza", "🍕"),
+    ("beer", "🍺"),
+    ("rocket", "🚀"),
+    ("sun", "☀️"),
+    ("rainbow", "🌈"),
+    ("check", "✅"),
+    ("x", "❌"),
+    ("warning", "⚠️"),
+    ("question", "❓"),
+];
+
+/// Emoji offered by the picker, grouped by category.
+pub const PICKER_CATEGORIES: &[(&str, &[&str])] = &[
+    (
+        "Smileys",
+        &[
+            "😀", "😄", "😂", "🤣", "😊", "😉", "😍", "😘", "😎", "🤔", "🙄", "😴", "😢", "😭",
+            "😱", "😡",
+        ],
+    ),
+    (
+        "Gestures",
+        &[
+            "👍", "👎", "👌", "👏", "👋", "🙏", "💪", "👀", "🤝", "✌️", "🤞", "🙌",
+        ],
+    ),
+    (
+        "Hearts",
+        &["❤️", "🧡", "💛", "💚", "💙", "💜", "🖤", "💔", "💖", "💯"],
+    ),
+    (
+        "Things",
+        &[
+            "🔥", "⭐", "✨", "🎉", "🥳", "🎁", "🍰", "☕", "🍕", "🍺", "🚀", "🌈",
+        ],
+    ),
+];
+
+/// Quick choices offered when reacting to a message.
+pub const REACTIONS: [&str; 6] = ["👍", "❤️", "😂", "😮", "😢", "🎉"];
+
+fn lookup(name: &str) -> Option<&'static str> {
+    SHORTCODES
+        .iter()
+        .find(|(code, _)| *code == name)
+        .map(|(_, emoji)| *emoji)
+}
+
+fn is_shortcode_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '+' || c == '-'
+}
+
+/// Replaces every known `:name:` with its emoji. Unknown codes are left as
+/// they are, and adjacent codes like `:fire::fire:` are each replaced.
+pub fn replace_shortcodes(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find(':') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let name_len = after
+            .find(|c: char| !is_shortcode_char(c))
+            .unwrap_or(after.len());
+
+        match lookup(&after[..name_len]) {
+            Some(emoji) if after[name_len..].starts_with(':') => {
+                out.push_str(emoji);
+                rest = &after[name_len + 1..];
+            }
+            _ => {
+                // Keep the colon; it may still open a code further on.
+                out.push(':');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_at_the_edges() {
+        assert_eq!(replace_shortcodes(":smile: hi"), "😄 hi");
+        assert_eq!(replace_shortcodes("hi :smile:"), "hi 😄");
+        assert_eq!(replace_shortcodes(":smile:"), "😄");
+    }
+
+    #[test]
+    fn adjacent_codes() {
+        assert_eq!(replace_shortcodes(":fire::fire:"), "🔥🔥");
+        assert_eq!(replace_shortcodes("a:smile::joy:b"), "a😄😂b");
+    }
+
+    #[test]
+    fn unknown_codes_are_kept() {
+        assert_eq!(replace_shortcodes(":nope:"), ":nope:");
+        assert_eq!(replace_shortcodes(":nope::smile:"), ":nope:😄");
+        assert_eq!(replace_shortcodes("at 10:30:00"), "at 10:30:00");
+        assert_eq!(replace_shortcodes("smile: :smile"), "smile: :smile");
+    }
+}
@@ -1,10 +1,73 @@
-use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use yew_agent::{Agent, AgentLink, Context, HandlerId};
 
-#[derive(Serialize, Deserialize, Debug)]
-pub enum Request {
-    EventBusMsg(String),
+use crate::services::websocket::{ConnectionState, MessageData, RosterEntry};
+
+/// Everything the socket layer tells the UI, already decoded from JSON.
+#[derive(Debug, Clone)]
+pub enum BusEvent {
+    Message(MessageData),
+    Users(Vec<RosterEntry>),
+    Typing {
+        username: String,
+        room: String,
+    },
+    /// A private message from `message.from` to `to`.
+    DirectMessage {
+        to: String,
+        message: MessageData,
+    },
+    /// The message with `id` now reads `message`.
+    Edit {
+        id: String,
+        message: String,
+        room: String,
+    },
+    /// `from` removed their message with `id`.
+    Delete {
+        id: String,
+        from: String,
+        room: String,
+    },
+    /// `from` toggled their `emoji` reaction on the message with `id`.
+    Reaction {
+        id: String,
+        emoji: String,
+        from: String,
+        room: String,
+    },
+    /// `from` pinned (or unpinned) the message with `id`.
+    Pin {
+        id: String,
+        pinned: bool,
+        from: String,
+        room: String,
+    },
+    /// Our message with `id` was delivered to its recipients.
+    Delivered(String),
+    /// `username` closed the app or logged out.
+    Left(String),
+    /// `username` went idle, or came back when `away` is false.
+    Presence {
+        username: String,
+        away: bool,
+    },
+    /// A batch of older messages for `room`, oldest first; empty when there are no more.
+    History {
+        room: String,
+        messages: Vec<MessageData>,
+    },
+    Status(ConnectionState),
+    /// Round-trip time to the server in milliseconds, averaged over recent heartbeats.
+    Latency(f64),
+    /// The socket dropped and a new connection attempt is scheduled.
+    Reconnecting(u32),
+    /// A frame from the server could not be decoded.
+    MalformedMessage,
+    /// An `Error` from the server, with a reason for the user.
+    ServerError(String),
+    /// The server refused our `Register`, with a reason for the user.
+    RegisterRejected(String),
 }
 
 pub struct EventBus {
@@ -15,8 +78,8 @@ pub struct EventBus {
 impl Agent for EventBus {
     type Reach = Context<Self>;
     type Message = ();
-    type Input = Request;
-    type Output = String;
+    type Input = BusEvent;
+    type Output = BusEvent;
 
     fn create(link: AgentLink<Self>) -> Self {
         Self {
@@ -28,12 +91,8 @@ impl Agent for EventBus {
     fn update(&mut self, _msg: Self::Message) {}
 
     fn handle_input(&mut self, msg: Self::Input, _id: HandlerId) {
-        match msg {
-            Request::EventBusMsg(s) => {
-                for sub in self.subscribers.iter() {
-                    self.link.respond(*sub, s.clone())
-                }
-            }
+        for sub in self.subscribers.iter() {
+            self.link.respond(*sub, msg.clone())
         }
     }
 
@@ -44,4 +103,4 @@ impl Agent for EventBus {
     fn disconnected(&mut self, id: HandlerId) {
         self.subscribers.remove(&id);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,109 @@
+//! Turning a conversation into a downloadable `.txt` or `.json` file.
+
+use serde::Serialize;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Blob, BlobPropertyBag, HtmlAnchorElement, Url};
+
+use crate::services::websocket::{MessageData, MessageKind};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Text,
+    Json,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Text => "txt",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    fn mime(self) -> &'static str {
+        match self {
+            ExportFormat::Text => "text/plain;charset=utf-8",
+            ExportFormat::Json => "application/json",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExportedMessage<'a> {
+    from: &'a str,
+    message: &'a str,
+    /// Milliseconds since the epoch.
+    timestamp: Option<f64>,
+    kind: MessageKind,
+}
+
+/// One line per message: `[2024-01-31 18:05] alice: hello`.
+pub fn to_text<'a>(messages: impl Iterator<Item = &'a MessageData>) -> String {
+    messages
+        .map(|m| {
+            let when = m.timestamp.map(format_timestamp).unwrap_or_default();
+            match m.kind {
+                MessageKind::User => format!("[{}] {}: {}\n", when, m.from, m.message),
+                MessageKind::Action => format!("[{}] * {} {}\n", when, m.from, m.message),
+                MessageKind::System => format!("[{}] -- {}\n", when, m.message),
+            }
+        })
+        .collect()
+}
+
+pub fn to_json<'a>(messages: impl Iterator<Item = &'a MessageData>) -> String {
+    let exported: Vec<ExportedMessage> = messages
+        .map(|m| ExportedMessage {
+            from: &m.from,
+            message: &m.message,
+            timestamp: m.timestamp,
+            kind: m.kind,
+        })
+        .collect();
+    serde_json::to_string_pretty(&exported).unwrap_or_default()
+}
+
+/// `chat-<name>-<YYYY-MM-DD>.<ext>`, dated today in local time.
+pub fn filename(name: &str, format: ExportFormat) -> String {
+    let today = format_timestamp(js_sys::Date::now());
+    format!("chat-{}-{}.{}", name, &today[..10], format.extension())
+}
+
+/// Serializes `messages` and has the browser save them as `filename`.
+pub fn download<'a>(
+    messages: impl Iterator<Item = &'a MessageData>,
+    format: ExportFormat,
+    filename: &str,
+) -> Result<(), JsValue> {
+    let contents = match format {
+        ExportFormat::Text => to_text(messages),
+        ExportFormat::Json => to_json(messages),
+    };
+
+    let parts = js_sys::Array::of1(&JsValue::from_str(&contents));
+    let mut options = BlobPropertyBag::new();
+    options.type_(format.mime());
+    let blob = Blob::new_with_str_sequence_and_options(&parts, &options)?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+
+    let anchor: HtmlAnchorElement = gloo::utils::document()
+        .create_element("a")?
+        .unchecked_into();
+    anchor.set_href(&url);
+    anchor.set_download(filename);
+    anchor.click();
+    Url::revoke_object_url(&url)
+}
+
+/// Local `YYYY-MM-DD HH:MM`.
+fn format_timestamp(ts: f64) -> String {
+    let date = js_sys::Date::new(&JsValue::from_f64(ts));
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        date.get_full_year(),
+        date.get_month() + 1,
+        date.get_date(),
+        date.get_hours(),
+        date.get_minutes()
+    )
+}
@@ -0,0 +1,89 @@
+//! Optional masking of swear words in displayed messages.
+
+/// Lowercase words hidden when the filter is on. Only whole words match,
+/// so "class" and "scrap" are left alone.
+const BLOCKED_WORDS: &[&str] = &[
+    "arse",
+    "arsehole",
+    "ass",
+    "asshole",
+    "bastard",
+    "bitch",
+    "bollocks",
+    "bullshit",
+    "crap",
+    "damn",
+    "dick",
+    "fuck",
+    "fucked",
+    "fucker",
+    "fucking",
+    "motherfucker",
+    "piss",
+    "pissed",
+    "shit",
+    "shitty",
+    "wanker",
+];
+
+/// Replaces every blocked word in `text` with one `*` per character,
+/// ignoring case. Everything else, including punctuation, is kept as is.
+pub fn mask_profanity(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(char::is_alphanumeric) {
+        out.push_str(&rest[..start]);
+        let word_start = &rest[start..];
+        let len = word_start
+            .find(|c: char| !c.is_alphanumeric())
+            .unwrap_or(word_start.len());
+        let word = &word_start[..len];
+        if is_blocked(word) {
+            out.extend(word.chars().map(|_| '*'));
+        } else {
+            out.push_str(word);
+        }
+        rest = &word_start[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn is_blocked(word: &str) -> bool {
+    let lower = word.to_lowercase();
+    BLOCKED_WORDS.contains(&lower.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_containing_blocked_words_are_kept() {
+        assert_eq!(
+            mask_profanity("a classic assessment"),
+            "a classic assessment"
+        );
+        assert_eq!(mask_profanity("Scrappy Dickens"), "Scrappy Dickens");
+    }
+
+    #[test]
+    fn masking_ignores_case() {
+        assert_eq!(mask_profanity("Damn"), "****");
+        assert_eq!(mask_profanity("oh CRAP"), "oh ****");
+        assert_eq!(mask_profanity("ShItTy day"), "****** day");
+    }
+
+    #[test]
+    fn punctuation_around_words_is_kept() {
+        assert_eq!(mask_profanity("well, damn!"), "well, ****!");
+        assert_eq!(mask_profanity("(crap)...crap"), "(****)...****");
+    }
+
+    #[test]
+    fn clean_text_is_unchanged() {
+        assert_eq!(mask_profanity(""), "");
+        assert_eq!(mask_profanity("  hello  there "), "  hello  there ");
+    }
+}
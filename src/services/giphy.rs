@@ -0,0 +1,100 @@
+//! A small client for the Giphy search API, used by the GIF picker.
+
+use std::fmt;
+
+use reqwasm::http::Request;
+use serde::Deserialize;
+
+/// API key, set at build time with `GIPHY_API_KEY`; the picker is disabled without one.
+const API_KEY: Option<&str> = option_env!("GIPHY_API_KEY");
+const SEARCH_URL: &str = "https://api.giphy.com/v1/gifs/search";
+const RESULT_LIMIT: u32 = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gif {
+    pub title: String,
+    /// Small rendition for the picker grid.
+    pub preview_url: String,
+    /// What gets sent as the message.
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GiphyError {
+    NotConfigured,
+    Network(String),
+    Status(u16),
+}
+
+impl fmt::Display for GiphyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GiphyError::NotConfigured => write!(f, "GIF search isn't configured"),
+            GiphyError::Network(error) => write!(f, "Couldn't reach Giphy: {}", error),
+            GiphyError::Status(status) => write!(f, "Giphy answered with HTTP {}", status),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    data: Vec<GifObject>,
+}
+
+#[derive(Deserialize)]
+struct GifObject {
+    #[serde(default)]
+    title: String,
+    images: Images,
+}
+
+#[derive(Deserialize)]
+struct Images {
+    fixed_height_small: Rendition,
+    fixed_height: Rendition,
+}
+
+#[derive(Deserialize)]
+struct Rendition {
+    url: String,
+}
+
+pub fn is_configured() -> bool {
+    API_KEY.is_some_and(|key| !key.is_empty())
+}
+
+/// Searches Giphy for `query`. An empty `Ok` means nothing matched.
+pub async fn search(query: &str) -> Result<Vec<Gif>, GiphyError> {
+    let key = API_KEY
+        .filter(|key| !key.is_empty())
+        .ok_or(GiphyError::NotConfigured)?;
+    let url = format!(
+        "{}?api_key={}&q={}&limit={}&rating=pg-13",
+        SEARCH_URL,
+        key,
+        String::from(js_sys::encode_uri_component(query)),
+        RESULT_LIMIT
+    );
+
+    let response = Request::get(&url)
+        .send()
+        .await
+        .map_err(|e| GiphyError::Network(e.to_string()))?;
+    if !response.ok() {
+        return Err(GiphyError::Status(response.status()));
+    }
+    let body: SearchResponse = response
+        .json()
+        .await
+        .map_err(|e| GiphyError::Network(e.to_string()))?;
+
+    Ok(body
+        .data
+        .into_iter()
+        .map(|gif| Gif {
+            title: gif.title,
+            preview_url: gif.images.fixed_height_small.url,
+            url: gif.images.fixed_height.url,
+        })
+        .collect())
+}
@@ -0,0 +1,215 @@
+//! A deliberately tiny markdown subset for chat messages: `**bold**`,
+//! `*italic*`, `` `code` `` and ```` ``` ```` fenced blocks. Everything else,
+//! including raw HTML, is passed through as text and escaped by Yew as usual.
+
+use yew::prelude::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Bold(Vec<Inline>),
+    Italic(Vec<Inline>),
+    Code(String),
+    /// A fenced block, kept verbatim with its line breaks.
+    CodeBlock(String),
+}
+
+/// Parses `input` into inline nodes. Unterminated or empty markers are kept
+/// as literal text rather than swallowing the rest of the message.
+pub fn parse(input: &str) -> Vec<Inline> {
+    let mut nodes = Vec::new();
+    let mut text = String::new();
+    let mut i = 0;
+
+    while i < input.len() {
+        let rest = &input[i..];
+
+        if let Some(after) = rest.strip_prefix("```") {
+            if let Some(end) = after.find("```") {
+                flush(&mut text, &mut nodes);
+                nodes.push(Inline::CodeBlock(fenced_body(&after[..end]).to_string()));
+                i += end + 6;
+                // The line break after the closing fence belongs to the block.
+                if input[i..].starts_with('\n') {
+                    i += 1;
+                }
+                continue;
+            }
+        }
+        if let Some(after) = rest.strip_prefix('`') {
+            if let Some(end) = after.find('`').filter(|&end| end > 0) {
+                flush(&mut text, &mut nodes);
+                nodes.push(Inline::Code(after[..end].to_string()));
+                i += end + 2;
+                continue;
+            }
+        } else if let Some(after) = rest.strip_prefix("**") {
+            if let Some(end) = find_closing(after, "**") {
+                flush(&mut text, &mut nodes);
+                nodes.push(Inline::Bold(parse(&after[..end])));
+                i += end + 4;
+                continue;
+            }
+            // Not bold; keep both stars so a lone `**` never turns into italics.
+            text.push_str("**");
+            i += 2;
+            continue;
+        } else if let Some(after) = rest.strip_prefix('*') {
+            if let Some(end) = find_closing(after, "*") {
+                flush(&mut text, &mut nodes);
+                nodes.push(Inline::Italic(parse(&after[..end])));
+                i += end + 2;
+                continue;
+            }
+        }
+
+        let c = rest.chars().next().unwrap_or_default();
+        text.push(c);
+        i += c.len_utf8();
+    }
+
+    flush(&mut text, &mut nodes);
+    nodes
+}
+
+/// Renders `input` as Yew `Html`, handing every plain-text run to `text` so
+/// callers can layer further formatting (e.g. links) on top.
+pub fn render(input: &str, text: &dyn Fn(&str) -> Html) -> Html {
+    render_nodes(&parse(input), text)
+}
+
+fn render_nodes(nodes: &[Inline], text: &dyn Fn(&str) -> Html) -> Html {
+    nodes
+        .iter()
+        .map(|node| match node {
+            Inline::Text(s) => text(s),
+            Inline::Bold(children) => html! { <strong>{render_nodes(children, text)}</strong> },
+            Inline::Italic(children) => html! { <em>{render_nodes(children, text)}</em> },
+            Inline::Code(code) => html! {
+                <code class="px-1 rounded bg-gray-200 font-mono text-[0.7rem]">{code.clone()}</code>
+            },
+            Inline::CodeBlock(code) => html! {
+                <pre class="my-1 p-2 rounded bg-gray-200 font-mono text-[0.7rem] whitespace-pre overflow-x-auto"><code>{code.clone()}</code></pre>
+            },
+        })
+        .collect::<Html>()
+}
+
+/// The text between two fences, minus an optional language tag on the
+/// opening line and the line breaks just inside the fences.
+fn fenced_body(block: &str) -> &str {
+    let block = match block.split_once('\n') {
+        Some((tag, body)) if !tag.contains(char::is_whitespace) => body,
+        _ => block,
+    };
+    block.strip_suffix('\n').unwrap_or(block)
+}
+
+fn flush(text: &mut String, nodes: &mut Vec<Inline>) {
+    if !text.is_empty() {
+        nodes.push(Inline::Text(std::mem::take(text)));
+    }
+}
+
+/// Finds the closing `delim` for an emphasis span, skipping code spans and,
+/// for single `*`, any `**` pairs nested inside. Like CommonMark, the span
+/// may not start or end with whitespace.
+fn find_closing(s: &str, delim: &str) -> Option<usize> {
+    if s.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let mut i = 0;
+    while i < s.len() {
+        let rest = &s[i..];
+        if let Some(after) = rest.strip_prefix('`') {
+            if let Some(end) = after.find('`') {
+                i += end + 2;
+                continue;
+            }
+        }
+        if delim == "*" && rest.starts_with("**") {
+            i += 2;
+            continue;
+        }
+        if rest.starts_with(delim) && i > 0 && !s[..i].ends_with(char::is_whitespace) {
+            return Some(i);
+        }
+        i += rest.chars().next().map_or(1, char::len_utf8);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Inline::*;
+
+    fn text(s: &str) -> Inline {
+        Text(s.to_string())
+    }
+
+    #[test]
+    fn nested_emphasis() {
+        assert_eq!(
+            parse("**bold *and italic* text**"),
+            [Bold(vec![
+                text("bold "),
+                Italic(vec![text("and italic")]),
+                text(" text")
+            ])]
+        );
+        assert_eq!(
+            parse("*italic **and bold** text*"),
+            [Italic(vec![
+                text("italic "),
+                Bold(vec![text("and bold")]),
+                text(" text")
+            ])]
+        );
+    }
+
+    #[test]
+    fn unclosed_markers_stay_literal() {
+        assert_eq!(parse("**not bold"), [text("**not bold")]);
+        assert_eq!(parse("*not italic"), [text("*not italic")]);
+        assert_eq!(parse("`not code"), [text("`not code")]);
+        assert_eq!(parse("a ** b"), [text("a ** b")]);
+    }
+
+    #[test]
+    fn stars_inside_code_are_literal() {
+        assert_eq!(parse("`a*b*c`"), [Code("a*b*c".to_string())]);
+        assert_eq!(
+            parse("*see `x*y`*"),
+            [Italic(vec![text("see "), Code("x*y".to_string())])]
+        );
+    }
+
+    #[test]
+    fn stray_delimiters() {
+        // Emphasis can't start or end with whitespace, as in CommonMark.
+        assert_eq!(parse("2 * 3 * 4"), [text("2 * 3 * 4")]);
+        assert_eq!(parse("* not a list *"), [text("* not a list *")]);
+        assert_eq!(parse("``"), [text("``")]);
+        assert_eq!(parse("****"), [text("****")]);
+    }
+
+    #[test]
+    fn fenced_blocks() {
+        assert_eq!(
+            parse("before\n```rust\nfn main() {}\n```\nafter"),
+            [
+                text("before\n"),
+                CodeBlock("fn main() {}".to_string()),
+                text("after")
+            ]
+        );
+        assert_eq!(parse("```a *b*```"), [CodeBlock("a *b*".to_string())]);
+    }
+
+    #[test]
+    fn html_is_plain_text() {
+        assert_eq!(parse("<b>hi</b>"), [text("<b>hi</b>")]);
+    }
+}
@@ -1,2 +1,12 @@
+pub mod clipboard;
+pub mod commands;
+pub mod emoji;
+pub mod event_bus;
+pub mod export;
+pub mod filter;
+pub mod giphy;
+pub mod markdown;
+pub mod notifications;
+pub mod storage;
+pub mod upload;
 pub mod websocket;
-pub mod event_bus;
\ No newline at end of file
@@ -0,0 +1,50 @@
+//! Thin wrappers around the browser Notifications API.
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Notification, NotificationOptions, NotificationPermission};
+
+/// Longest message body shown in a notification, in characters.
+const BODY_MAX_CHARS: usize = 120;
+
+/// Whether the page is currently in a background tab or minimised.
+pub fn page_hidden() -> bool {
+    web_sys::window()
+        .and_then(|w| w.document())
+        .map(|d| d.hidden())
+        .unwrap_or(false)
+}
+
+/// Asks for permission if the user hasn't decided yet. Browsers only show the
+/// prompt in response to a user gesture, so call this from an event handler.
+pub fn request_permission() {
+    if Notification::permission() == NotificationPermission::Default {
+        let _ = Notification::request_permission();
+    }
+}
+
+/// Shows a notification if permitted; clicking it focuses the chat window.
+pub fn show(title: &str, body: &str) {
+    if Notification::permission() != NotificationPermission::Granted {
+        return;
+    }
+
+    let body = if body.chars().count() > BODY_MAX_CHARS {
+        format!("{}…", body.chars().take(BODY_MAX_CHARS).collect::<String>())
+    } else {
+        body.to_string()
+    };
+    let mut options = NotificationOptions::new();
+    options.body(&body);
+
+    if let Ok(notification) = Notification::new_with_options(title, &options) {
+        let handle = notification.clone();
+        let onclick = Closure::once_into_js(move || {
+            if let Some(window) = web_sys::window() {
+                let _ = window.focus();
+            }
+            handle.close();
+        });
+        notification.set_onclick(Some(onclick.unchecked_ref()));
+    }
+}
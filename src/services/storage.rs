@@ -0,0 +1,123 @@
+//! Typed access to localStorage. Every value is JSON under a namespaced,
+//! versioned key like `yewchat.settings.v1`, so it can't collide with other
+//! apps on the same origin and can change shape without misreading old data.
+
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use web_sys::Storage;
+
+use crate::local_storage;
+
+const NAMESPACE: &str = "yewchat";
+
+/// Something kept in localStorage under its own key.
+pub trait Stored: Serialize + DeserializeOwned + Default {
+    /// Unique within the app, e.g. `"settings"`.
+    const NAME: &'static str;
+    /// Bumped whenever the stored shape changes in a way serde's defaults
+    /// can't absorb. Older versions are passed through [`migrate`](Self::migrate).
+    const VERSION: u32;
+
+    /// Upgrades JSON saved under an older `version`. The default reads it as
+    /// is, which suits changes that only add fields; `None` starts over.
+    fn migrate(_version: u32, json: &str) -> Option<Self> {
+        serde_json::from_str(json).ok()
+    }
+
+    /// Reads the value from wherever it was kept before this module existed,
+    /// removing it from there (see [`take_legacy`]); it is re-saved under
+    /// the current key straight after.
+    fn legacy(_storage: &Storage) -> Option<Self> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageError {
+    /// Storage is disabled, e.g. in some private browsing modes.
+    Unavailable,
+    /// The browser refused the write, almost always because the quota is used up.
+    Rejected,
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Unavailable => write!(f, "Browser storage isn't available"),
+            StorageError::Rejected => write!(f, "Browser storage is full"),
+        }
+    }
+}
+
+fn key(name: &str, version: u32) -> String {
+    format!("{}.{}.v{}", NAMESPACE, name, version)
+}
+
+/// The stored `T`, migrated from an older version if that's all there is.
+/// Missing, unreadable or unavailable values give `T::default()`.
+pub fn get<T: Stored>() -> T {
+    let Some(storage) = local_storage() else {
+        return T::default();
+    };
+    if let Some(json) = storage.get_item(&key(T::NAME, T::VERSION)).ok().flatten() {
+        return serde_json::from_str(&json).unwrap_or_else(|e| {
+            log::warn!("discarding unreadable {}: {}", T::NAME, e);
+            T::default()
+        });
+    }
+
+    for version in (1..T::VERSION).rev() {
+        let old_key = key(T::NAME, version);
+        if let Some(json) = storage.get_item(&old_key).ok().flatten() {
+            let value = T::migrate(version, &json).unwrap_or_default();
+            if set(&value).is_ok() {
+                let _ = storage.remove_item(&old_key);
+            }
+            return value;
+        }
+    }
+
+    match T::legacy(&storage) {
+        Some(value) => {
+            let _ = set(&value);
+            value
+        }
+        None => T::default(),
+    }
+}
+
+pub fn set<T: Stored>(value: &T) -> Result<(), StorageError> {
+    let storage = local_storage().ok_or(StorageError::Unavailable)?;
+    let json = serde_json::to_string(value).expect("stored values serialize to JSON");
+    storage
+        .set_item(&key(T::NAME, T::VERSION), &json)
+        .map_err(|_| {
+            log::warn!("couldn't save {}: storage is full", T::NAME);
+            StorageError::Rejected
+        })
+}
+
+/// Deletes the stored `T`, so the next [`get`] gives `T::default()`.
+pub fn remove<T: Stored>() {
+    if let Some(storage) = local_storage() {
+        let _ = storage.remove_item(&key(T::NAME, T::VERSION));
+    }
+}
+
+/// Reads an item saved under a legacy key and removes it.
+pub fn take_legacy(storage: &Storage, key: &str) -> Option<String> {
+    let value = storage.get_item(key).ok().flatten()?;
+    let _ = storage.remove_item(key);
+    Some(value)
+}
+
+/// Every legacy key starting with `prefix`.
+pub fn legacy_keys(storage: &Storage, prefix: &str) -> Vec<String> {
+    let len = storage.length().unwrap_or(0);
+    (0..len)
+        .filter_map(|i| storage.key(i).ok().flatten())
+        .filter(|key| key.starts_with(prefix))
+        .collect()
+}
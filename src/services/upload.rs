@@ -0,0 +1,99 @@
+//! Uploads dropped or pasted images so they can be sent as links.
+
+use std::fmt;
+
+use reqwasm::http::Request;
+use serde::Deserialize;
+use web_sys::{File, FormData};
+
+/// Endpoint, set at build time with `CHAT_UPLOAD_URL`; image uploads are
+/// disabled without one. It receives a multipart `POST` with the image in a
+/// `file` field and answers with `{"url": "..."}`; links ending in an image
+/// extension are shown inline.
+const UPLOAD_URL: Option<&str> = option_env!("CHAT_UPLOAD_URL");
+/// Largest image accepted, checked before anything is sent.
+pub const MAX_UPLOAD_BYTES: f64 = 5.0 * 1024.0 * 1024.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UploadError {
+    NotConfigured,
+    NotAnImage,
+    TooLarge,
+    Network(String),
+    Status(u16),
+    /// The server answered without a usable link.
+    BadResponse,
+}
+
+impl UploadError {
+    /// Whether trying the same file again might work.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            UploadError::Network(_) | UploadError::Status(_) | UploadError::BadResponse
+        )
+    }
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadError::NotConfigured => write!(f, "Image upload isn't configured"),
+            UploadError::NotAnImage => write!(f, "Only images can be uploaded"),
+            UploadError::TooLarge => write!(
+                f,
+                "Images can be at most {} MB",
+                MAX_UPLOAD_BYTES / (1024.0 * 1024.0)
+            ),
+            UploadError::Network(error) => write!(f, "Upload failed: {}", error),
+            UploadError::Status(status) => write!(f, "Upload failed with HTTP {}", status),
+            UploadError::BadResponse => write!(f, "The upload server sent an unexpected answer"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct UploadResponse {
+    url: String,
+}
+
+pub fn is_configured() -> bool {
+    UPLOAD_URL.is_some_and(|url| !url.is_empty())
+}
+
+pub fn is_image(file: &File) -> bool {
+    file.type_().starts_with("image/")
+}
+
+/// Uploads `file` and returns the link it can be seen at.
+pub async fn upload(file: &File) -> Result<String, UploadError> {
+    let endpoint = UPLOAD_URL
+        .filter(|url| !url.is_empty())
+        .ok_or(UploadError::NotConfigured)?;
+    if !is_image(file) {
+        return Err(UploadError::NotAnImage);
+    }
+    if file.size() > MAX_UPLOAD_BYTES {
+        return Err(UploadError::TooLarge);
+    }
+
+    let form = FormData::new()
+        .and_then(|form| {
+            form.append_with_blob_and_filename("file", file, &file.name())
+                .map(|()| form)
+        })
+        .map_err(|_| UploadError::Network("couldn't attach the file".to_string()))?;
+    let response = Request::post(endpoint)
+        .body(form)
+        .send()
+        .await
+        .map_err(|e| UploadError::Network(e.to_string()))?;
+    if !response.ok() {
+        return Err(UploadError::Status(response.status()));
+    }
+    let body: UploadResponse = response
+        .json()
+        .await
+        .map_err(|_| UploadError::BadResponse)?;
+    Ok(body.url)
+}
@@ -1,53 +1,953 @@
-use futures::{channel::mpsc::Sender, SinkExt, StreamExt};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::rc::Rc;
+
+use futures::channel::mpsc::{Receiver, Sender};
+use futures::channel::oneshot;
+use futures::future::{self, Either};
+use futures::{SinkExt, StreamExt};
+use gloo::timers::future::{IntervalStream, TimeoutFuture};
 use reqwasm::websocket::{futures::WebSocket, Message};
+use serde::{Deserialize, Serialize};
 
 use wasm_bindgen_futures::spawn_local;
-use yew_agent::Dispatched;
+use yew_agent::{Dispatched, Dispatcher};
+
+use crate::services::event_bus::{BusEvent, EventBus};
+
+/// Server endpoint, overridable at build time with `CHAT_WS_URL`.
+const WS_URL: &str = match option_env!("CHAT_WS_URL") {
+    Some(url) => url,
+    None => "ws://127.0.0.1:8080",
+};
+pub const DEFAULT_ROOM: &str = "general";
+const BACKOFF_BASE_MS: u32 = 1_000;
+const BACKOFF_MAX_MS: u32 = 30_000;
+/// How many outgoing messages are kept while the socket is down.
+const OUTBOUND_QUEUE_CAP: usize = 100;
+/// How many undecodable frames are kept for inspection.
+const PARSE_FAILURE_LOG_CAP: usize = 20;
+/// Heartbeat period; comfortably below the ~60s idle timeout of common proxies.
+const HEARTBEAT_INTERVAL_MS: u32 = 25_000;
+/// Round trips averaged into the reported latency.
+const LATENCY_SAMPLES: usize = 5;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MsgTypes {
+    Users,
+    Register,
+    Message,
+    Typing,
+    /// A user closed the app or logged out: `data` holds their username.
+    /// Relayed to everyone else so they drop out of the list straight away.
+    Leave,
+    JoinRoom,
+    /// Heartbeat sent by the client to keep idle connections open. Its
+    /// `timestamp` is when it was sent, for measuring latency.
+    Ping,
+    /// The server's answer to `Ping`, echoing its `timestamp` unchanged.
+    /// Servers that don't answer simply leave latency unknown.
+    Pong,
+    /// Replaces the text of an earlier message: `id` names the message and
+    /// `data` holds the new text.
+    Edit,
+    /// Removes an earlier message: `id` names the message and `data` holds
+    /// the username of its author.
+    Delete,
+    /// Toggles `from`'s reaction on a message: `id` names the message and
+    /// `data` holds the emoji.
+    Reaction,
+    /// A private message: `from` and `to` name the two users, `data` holds the
+    /// text, and `id`/`timestamp`/`kind` work as for `Message`.
+    DirectMessage,
+    /// The server's answer to `Register`:
+    /// `{"messageType": "registerResult", "data": "ok"}` on success, or
+    /// `{"messageType": "registerResult", "data": "taken"}` when someone else
+    /// already uses the name. Any other `data` is treated as a rejection
+    /// reason and shown as is. Servers that never send this accept everyone.
+    RegisterResult,
+    /// Asks for messages older than a cursor, and carries the answer.
+    /// Request: `{"messageType": "history", "room": "general", "id": "<oldest id>",
+    /// "timestamp": <oldest timestamp>}`, where either cursor field may be
+    /// missing. Response: `{"messageType": "history", "room": "general",
+    /// "dataArray": [...]}`, each entry a `Message`-style JSON string, oldest
+    /// first. An empty `dataArray` means there is nothing older.
+    History,
+    /// Pins or unpins a message for everyone in the room: `id` names the
+    /// message, `data` is `"pin"` or `"unpin"` and `from` who did it.
+    Pin,
+    /// Whether a user is at their keyboard: `from` names the user and `data`
+    /// is `"away"` after a few idle minutes or `"active"` once they are back.
+    Presence,
+    /// Our message reached the people it was meant for: `id` names the
+    /// message. Sent after the server's echo of it, if at all; without acks,
+    /// messages simply stay marked as sent.
+    Ack,
+    /// Something the server couldn't do, e.g. a rejected or rate-limited
+    /// message: `data` holds the reason, meant to be shown as is.
+    Error,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSocketMessage {
+    pub message_type: MsgTypes,
+    pub data_array: Option<Vec<String>>,
+    pub data: Option<String>,
+    pub timestamp: Option<f64>,
+    /// Client-generated id, echoed back so sent messages can be reconciled.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Room the message belongs to; `None` means the default room.
+    #[serde(default)]
+    pub room: Option<String>,
+    /// Id of the message this one replies to.
+    #[serde(default)]
+    pub reply_to: Option<String>,
+    /// Who sent a `Reaction` or `DirectMessage`.
+    #[serde(default)]
+    pub from: Option<String>,
+    /// Recipient of a `DirectMessage`.
+    #[serde(default)]
+    pub to: Option<String>,
+    /// Set on `/me` actions so receivers render them as such.
+    #[serde(default)]
+    pub kind: Option<MessageKind>,
+    /// Our Dicebear style, sent with `Register`.
+    #[serde(default)]
+    pub avatar_style: Option<String>,
+    /// Dicebear style for each entry of `data_array` in `Users` messages, if
+    /// the server relays them.
+    #[serde(default)]
+    pub avatar_styles: Option<Vec<String>>,
+}
+
+impl WebSocketMessage {
+    pub fn new(message_type: MsgTypes, data: Option<String>) -> Self {
+        Self {
+            message_type,
+            data_array: None,
+            data,
+            timestamp: None,
+            id: None,
+            room: None,
+            reply_to: None,
+            from: None,
+            to: None,
+            kind: None,
+            avatar_style: None,
+            avatar_styles: None,
+        }
+    }
+
+    pub fn room(&self) -> &str {
+        self.room.as_deref().unwrap_or(DEFAULT_ROOM)
+    }
+}
+
+/// One entry of the server's user list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RosterEntry {
+    pub name: String,
+    /// `None` when the server doesn't relay styles or the user never chose one.
+    pub avatar_style: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageKind {
+    #[default]
+    User,
+    /// A `/me` action, shown as "* Alice dances".
+    Action,
+    /// Generated locally, e.g. join/leave notices.
+    System,
+}
+
+/// Delivery state of a message we sent.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SendStatus {
+    /// Handed to the socket; waiting for the server's echo.
+    Sending,
+    /// Echoed back by the server, or not ours to begin with.
+    #[default]
+    Sent,
+    /// Acknowledged by the server as delivered to its recipients.
+    Delivered,
+    /// Couldn't be queued for sending; can be retried.
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MessageData {
+    pub from: String,
+    pub message: String,
+    /// Milliseconds since the epoch; filled in on receipt if the server omits it.
+    pub timestamp: Option<f64>,
+    #[serde(default)]
+    pub kind: MessageKind,
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Room the message was posted in; `None` means the default room.
+    #[serde(default)]
+    pub room: Option<String>,
+    #[serde(skip)]
+    pub status: SendStatus,
+    /// The text was changed after sending.
+    #[serde(default)]
+    pub edited: bool,
+    /// Id of the message this one replies to.
+    #[serde(default)]
+    pub reply_to: Option<String>,
+}
+
+impl MessageData {
+    pub fn system(message: String) -> Self {
+        Self {
+            from: String::new(),
+            message,
+            timestamp: Some(js_sys::Date::now()),
+            kind: MessageKind::System,
+            id: None,
+            room: None,
+            status: SendStatus::Sent,
+            edited: false,
+            reply_to: None,
+        }
+    }
+
+    pub fn room(&self) -> &str {
+        self.room.as_deref().unwrap_or(DEFAULT_ROOM)
+    }
+}
+
+/// Where the socket is in its lifecycle, published on the bus as `Status`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ConnectionState {
+    /// Opening the socket, for the first time or after a backoff.
+    Connecting,
+    Connected,
+    /// Dropped, and waiting out the backoff before trying again.
+    Reconnecting,
+    /// Closed for good, or just dropped and about to start reconnecting.
+    Disconnected,
+}
+
+/// A server frame that could not be decoded, kept for debugging.
+#[derive(Debug, Clone)]
+pub struct ParseFailure {
+    pub payload: String,
+    pub error: String,
+    pub at: f64,
+}
+
+/// State shared between the service handle and its background task.
+struct Shared {
+    register: RefCell<Option<String>>,
+    state: Cell<ConnectionState>,
+    failures: RefCell<VecDeque<ParseFailure>>,
+    failure_count: Cell<usize>,
+    /// Cuts the current reconnect backoff short; only set while waiting.
+    wake: RefCell<Option<oneshot::Sender<()>>>,
+    /// Most recent round-trip times in milliseconds, oldest first.
+    latency: RefCell<VecDeque<f64>>,
+}
+
+impl Shared {
+    fn new() -> Self {
+        Self {
+            register: RefCell::new(None),
+            state: Cell::new(ConnectionState::Connecting),
+            failures: RefCell::new(VecDeque::new()),
+            failure_count: Cell::new(0),
+            wake: RefCell::new(None),
+            latency: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    fn record_latency(&self, rtt: f64) {
+        let mut samples = self.latency.borrow_mut();
+        if samples.len() >= LATENCY_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(rtt);
+    }
+
+    fn average_latency(&self) -> Option<f64> {
+        let samples = self.latency.borrow();
+        (!samples.is_empty()).then(|| samples.iter().sum::<f64>() / samples.len() as f64)
+    }
+
+    /// Keeps the last `PARSE_FAILURE_LOG_CAP` failures; `at` is when the frame arrived.
+    fn record_failure(&self, payload: &str, error: String, at: f64) {
+        log::warn!("malformed server message: {} ({})", payload, error);
+        let mut failures = self.failures.borrow_mut();
+        if failures.len() >= PARSE_FAILURE_LOG_CAP {
+            failures.pop_front();
+        }
+        failures.push_back(ParseFailure {
+            payload: payload.to_string(),
+            error,
+            at,
+        });
+        self.failure_count.set(self.failure_count.get() + 1);
+    }
+}
+
+/// How a [`WebsocketService`] connects and recovers. The [`Default`] is what
+/// [`WebsocketService::new`] uses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebsocketServiceConfig {
+    pub url: String,
+    /// Reconnect with backoff after the socket drops or fails to open;
+    /// without it the service stays disconnected after the first failure.
+    pub reconnect: bool,
+    /// Delay before the first reconnect attempt, doubled for each one after.
+    pub backoff_base_ms: u32,
+    /// Longest delay between reconnect attempts.
+    pub backoff_max_ms: u32,
+    pub heartbeat_interval_ms: u32,
+    /// How many outgoing messages are kept while the socket is down.
+    pub outbound_queue_cap: usize,
+}
+
+impl Default for WebsocketServiceConfig {
+    fn default() -> Self {
+        Self {
+            url: WS_URL.to_string(),
+            reconnect: true,
+            backoff_base_ms: BACKOFF_BASE_MS,
+            backoff_max_ms: BACKOFF_MAX_MS,
+            heartbeat_interval_ms: HEARTBEAT_INTERVAL_MS,
+            outbound_queue_cap: OUTBOUND_QUEUE_CAP,
+        }
+    }
+}
 
-use crate::services::event_bus::{EventBus, Request};
+impl WebsocketServiceConfig {
+    /// The defaults, but connecting to `url`.
+    pub fn for_url(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Delay before reconnect attempt `attempt` (1-based): the base delay,
+    /// doubled per attempt and capped at the maximum.
+    fn backoff_delay(&self, attempt: u32) -> u32 {
+        let exp = attempt.saturating_sub(1).min(16);
+        self.backoff_base_ms
+            .saturating_mul(1 << exp)
+            .min(self.backoff_max_ms)
+    }
+}
 
 pub struct WebsocketService {
     pub tx: Sender<String>,
+    config: WebsocketServiceConfig,
+    shared: Rc<Shared>,
 }
 
 impl WebsocketService {
     pub fn new() -> Self {
-        let ws = WebSocket::open("ws://127.0.0.1:8080").unwrap();
+        Self::connect(WS_URL)
+    }
+
+    pub fn connect(url: &str) -> Self {
+        Self::with_config(WebsocketServiceConfig::for_url(url))
+    }
+
+    pub fn with_config(config: WebsocketServiceConfig) -> Self {
+        let (service, task) = Self::unstarted(config);
+        spawn_local(task);
+        service
+    }
+
+    /// A service that never connects, for when there is nobody to sign in.
+    /// It reports [`ConnectionState::Disconnected`] and drops whatever is sent.
+    pub fn offline() -> Self {
+        let (service, _task) = Self::unstarted(WebsocketServiceConfig::for_url(WS_URL));
+        service.shared.state.set(ConnectionState::Disconnected);
+        service.close();
+        service
+    }
+
+    /// The service along with the socket task that `with_config` would spawn.
+    /// Nothing connects until the task is polled.
+    fn unstarted(config: WebsocketServiceConfig) -> (Self, impl Future<Output = ()>) {
+        let (in_tx, in_rx) = futures::channel::mpsc::channel::<String>(1000);
+        let shared = Rc::new(Shared::new());
+        let task = run(config.clone(), in_rx, shared.clone());
+
+        let service = Self {
+            tx: in_tx,
+            config,
+            shared,
+        };
+        (service, task)
+    }
+
+    pub fn url(&self) -> &str {
+        &self.config.url
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.shared.state.get()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state() == ConnectionState::Connected
+    }
+
+    /// Sets the message sent first on every (re)connect, so the server learns
+    /// who we are again after a dropped connection.
+    pub fn register(&self, message: String) {
+        *self.shared.register.borrow_mut() = Some(message);
+    }
+
+    /// The most recent frames that failed to decode, oldest first.
+    pub fn parse_failures(&self) -> Vec<ParseFailure> {
+        self.shared.failures.borrow().iter().cloned().collect()
+    }
+
+    /// Total number of undecodable frames since the service started.
+    pub fn parse_failure_count(&self) -> usize {
+        self.shared.failure_count.get()
+    }
+
+    /// Average round-trip time of the last few heartbeats in milliseconds,
+    /// or `None` until the server has answered one.
+    pub fn latency(&self) -> Option<f64> {
+        self.shared.average_latency()
+    }
+
+    /// Skips the rest of the reconnect backoff and tries again right away.
+    /// Does nothing unless a reconnect is pending, so repeated calls don't
+    /// start extra attempts.
+    pub fn reconnect_now(&self) {
+        if let Some(wake) = self.shared.wake.borrow_mut().take() {
+            let _ = wake.send(());
+        }
+    }
+
+    /// Says goodbye to the server, then closes like [`close`](Self::close).
+    /// The socket task writes the message in the microtask that follows, so
+    /// it still goes out when called from a `beforeunload` handler.
+    pub fn leave(&self, username: &str) {
+        let message = WebSocketMessage::new(MsgTypes::Leave, Some(username.to_string()));
+        let _ = self
+            .tx
+            .clone()
+            .try_send(serde_json::to_string(&message).unwrap());
+        self.close();
+    }
+
+    /// Stops reconnecting and closes the socket once everything already sent
+    /// has been flushed.
+    pub fn close(&self) {
+        self.tx.clone().close_channel();
+    }
+}
+
+/// Messages waiting for an open socket. Once `capacity` is reached the
+/// oldest entry is dropped to make room.
+struct OutboundQueue {
+    items: VecDeque<String>,
+    capacity: usize,
+}
+
+impl OutboundQueue {
+    fn new(capacity: usize) -> Self {
+        Self {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    fn push(&mut self, message: String) {
+        if self.items.len() >= self.capacity {
+            if let Some(dropped) = self.items.pop_front() {
+                log::warn!("outbound queue full, dropping {}", dropped);
+            }
+        }
+        self.items.push_back(message);
+    }
+
+    /// The oldest waiting message, left in place until [`sent`](Self::sent)
+    /// confirms it went out, so a failed write keeps it for the next socket.
+    fn front(&self) -> Option<&str> {
+        self.items.front().map(String::as_str)
+    }
+
+    /// Drops the message returned by [`front`](Self::front) once it has been written.
+    fn sent(&mut self) {
+        self.items.pop_front();
+    }
+}
+
+/// Moves everything sent by the owner into `queue` until the channel closes.
+async fn buffer(outgoing: &mut Receiver<String>, queue: &mut OutboundQueue) {
+    while let Some(s) = outgoing.next().await {
+        log::debug!("queued while offline: {}", s);
+        queue.push(s);
+    }
+}
+
+/// Keeps a socket open for as long as the owning `WebsocketService` is alive,
+/// reconnecting with exponential backoff whenever it drops (unless the config
+/// turns that off). Anything sent while the socket is down is queued and
+/// flushed in order after the next handshake.
+async fn run(config: WebsocketServiceConfig, mut outgoing: Receiver<String>, shared: Rc<Shared>) {
+    let mut event_bus = EventBus::dispatcher();
+    let mut queue = OutboundQueue::new(config.outbound_queue_cap);
+    let mut attempt = 0;
+
+    loop {
+        if attempt > 0 {
+            if !config.reconnect {
+                break;
+            }
+            let delay = config.backoff_delay(attempt);
+            log::debug!("reconnecting in {}ms (attempt {})", delay, attempt);
+            event_bus.send(BusEvent::Reconnecting(attempt));
+            publish(&mut event_bus, &shared.state, ConnectionState::Reconnecting);
+            let (wake, woken) = oneshot::channel();
+            *shared.wake.borrow_mut() = Some(wake);
+            let backoff = future::select(TimeoutFuture::new(delay), woken);
+            let waiting = buffer(&mut outgoing, &mut queue);
+            let stopped = matches!(
+                future::select(backoff, Box::pin(waiting)).await,
+                Either::Right(_)
+            );
+            shared.wake.borrow_mut().take();
+            if stopped {
+                break;
+            }
+        }
+        publish(&mut event_bus, &shared.state, ConnectionState::Connecting);
+
+        let ws = match WebSocket::open(&config.url) {
+            Ok(ws) => ws,
+            Err(e) => {
+                log::error!("ws: {:?}", e);
+                publish(&mut event_bus, &shared.state, ConnectionState::Disconnected);
+                attempt += 1;
+                continue;
+            }
+        };
 
         let (mut write, mut read) = ws.split();
+        let mut connected = false;
 
-        let (in_tx, mut in_rx) = futures::channel::mpsc::channel::<String>(1000);
-        let mut event_bus = EventBus::dispatcher();
+        let writer = async {
+            // The sink only becomes ready once the socket has opened.
+            let ready = future::poll_fn(|cx| write.poll_ready_unpin(cx));
+            let waiting = buffer(&mut outgoing, &mut queue);
+            match future::select(ready, Box::pin(waiting)).await {
+                Either::Left((Ok(()), _)) => {}
+                Either::Left((Err(_), _)) => return true,
+                Either::Right(_) => return false,
+            }
+            connected = true;
+            publish(&mut event_bus, &shared.state, ConnectionState::Connected);
 
-        spawn_local(async move {
-            while let Some(s) = in_rx.next().await {
-                log::debug!("got event from channel! {}", s);
-                write.send(Message::Text(s)).await.unwrap();
+            let handshake = shared.register.borrow().clone();
+            if let Some(s) = handshake {
+                if write.send(Message::Text(s)).await.is_err() {
+                    return true;
+                }
             }
-        });
 
-        spawn_local(async move {
-            while let Some(msg) = read.next().await {
-                match msg {
-                    Ok(Message::Text(data)) => {
-                        log::debug!("from websocket: {}", data);
-                        event_bus.send(Request::EventBusMsg(data));
+            // Created per connection, so it stops with the socket and restarts on reconnect.
+            let mut heartbeat = IntervalStream::new(config.heartbeat_interval_ms);
+            // One straight away, so latency is known without waiting a full interval.
+            if write.send(Message::Text(ping())).await.is_err() {
+                return true;
+            }
+
+            loop {
+                while let Some(s) = queue.front() {
+                    log::debug!("got event from channel! {}", s);
+                    if let Err(e) = write.send(Message::Text(s.to_string())).await {
+                        log::error!("ws: {:?}", e);
+                        return true;
                     }
-                    Ok(Message::Bytes(b)) => {
-                        let decoded = std::str::from_utf8(&b);
-                        if let Ok(val) = decoded {
-                            log::debug!("from websocket: {}", val);
-                            event_bus.send(Request::EventBusMsg(val.into()));
+                    queue.sent();
+                }
+                match future::select(outgoing.next(), heartbeat.next()).await {
+                    Either::Left((Some(s), _)) => queue.push(s),
+                    Either::Left((None, _)) => return false,
+                    Either::Right(_) => {
+                        if write.send(Message::Text(ping())).await.is_err() {
+                            return true;
                         }
                     }
-                    Err(e) => {
-                        log::error!("ws: {:?}", e)
-                    }
                 }
             }
+        };
+
+        let mut bus = EventBus::dispatcher();
+        let reader_shared = shared.clone();
+        let reader = async move {
+            while let Some(msg) = read.next().await {
+                dispatch(&mut bus, &reader_shared, msg);
+            }
             log::debug!("WebSocket Closed");
-        });
+        };
+
+        let keep_alive = match future::select(Box::pin(writer), Box::pin(reader)).await {
+            Either::Left((keep_alive, _)) => keep_alive,
+            Either::Right(_) => true,
+        };
+
+        if !keep_alive {
+            // Every sender is gone, so nobody is listening any more.
+            break;
+        }
+        publish(&mut event_bus, &shared.state, ConnectionState::Disconnected);
+        attempt = if connected { 1 } else { attempt + 1 };
+    }
+    shared.state.set(ConnectionState::Disconnected);
+}
+
+/// A heartbeat stamped with the current time.
+fn ping() -> String {
+    let ping = WebSocketMessage {
+        timestamp: Some(js_sys::Date::now()),
+        ..WebSocketMessage::new(MsgTypes::Ping, None)
+    };
+    serde_json::to_string(&ping).unwrap()
+}
+
+/// Records the new state for synchronous queries and broadcasts it.
+fn publish(
+    event_bus: &mut Dispatcher<EventBus>,
+    state: &Cell<ConnectionState>,
+    new_state: ConnectionState,
+) {
+    state.set(new_state);
+    event_bus.send(BusEvent::Status(new_state));
+}
+
+fn dispatch(
+    event_bus: &mut Dispatcher<EventBus>,
+    shared: &Shared,
+    msg: Result<Message, reqwasm::websocket::WebSocketError>,
+) {
+    let data = match msg {
+        Ok(Message::Text(data)) => data,
+        Ok(Message::Bytes(b)) => match String::from_utf8(b) {
+            Ok(val) => val,
+            Err(_) => return,
+        },
+        Err(e) => {
+            log::error!("ws: {:?}", e);
+            return;
+        }
+    };
+    log::debug!("from websocket: {}", data);
+
+    if let Some(event) = route(shared, &data, js_sys::Date::now()) {
+        event_bus.send(event);
+    }
+}
 
-        Self { tx: in_tx }
+/// What, if anything, a frame received at `now` puts on the bus. Latency
+/// samples are averaged first, and undecodable frames are logged in `shared`
+/// with only a `MalformedMessage` notice going out.
+fn route(shared: &Shared, data: &str, now: f64) -> Option<BusEvent> {
+    match decode(data) {
+        Ok(Some(BusEvent::Latency(rtt))) => {
+            shared.record_latency(rtt);
+            shared.average_latency().map(BusEvent::Latency)
+        }
+        Ok(Some(event)) => Some(event),
+        Ok(None) => {
+            log::debug!("ignoring message: {}", data);
+            None
+        }
+        Err(e) => {
+            shared.record_failure(data, e.to_string(), now);
+            Some(BusEvent::MalformedMessage)
+        }
     }
-}
\ No newline at end of file
+}
+
+/// Turns a raw server frame into a typed bus event, so subscribers never
+/// have to deal with JSON. `Ok(None)` means a well-formed frame we don't act on.
+fn decode(data: &str) -> Result<Option<BusEvent>, serde_json::Error> {
+    let msg = serde_json::from_str::<WebSocketMessage>(data)?;
+    let room = msg.room().to_string();
+
+    Ok(match msg.message_type {
+        MsgTypes::Users => {
+            let mut styles = msg.avatar_styles.unwrap_or_default().into_iter();
+            let entries = msg
+                .data_array
+                .unwrap_or_default()
+                .into_iter()
+                .map(|name| RosterEntry {
+                    name,
+                    avatar_style: styles.next(),
+                })
+                .collect();
+            Some(BusEvent::Users(entries))
+        }
+        MsgTypes::Message => {
+            let raw = match msg.data.as_deref() {
+                Some(raw) => raw,
+                None => return Ok(None),
+            };
+            let mut message_data = serde_json::from_str::<MessageData>(raw)?;
+            let received_at = msg.timestamp.unwrap_or_else(js_sys::Date::now);
+            message_data.timestamp.get_or_insert(received_at);
+            if message_data.id.is_none() {
+                message_data.id = msg.id;
+            }
+            if message_data.reply_to.is_none() {
+                message_data.reply_to = msg.reply_to;
+            }
+            if message_data.kind == MessageKind::User && msg.kind == Some(MessageKind::Action) {
+                message_data.kind = MessageKind::Action;
+            }
+            message_data.room.get_or_insert(room);
+            Some(BusEvent::Message(message_data))
+        }
+        MsgTypes::Typing => msg.data.map(|username| BusEvent::Typing { username, room }),
+        MsgTypes::Edit => match (msg.id, msg.data) {
+            (Some(id), Some(message)) => Some(BusEvent::Edit { id, message, room }),
+            _ => None,
+        },
+        MsgTypes::Delete => match (msg.id, msg.data) {
+            (Some(id), Some(from)) => Some(BusEvent::Delete { id, from, room }),
+            _ => None,
+        },
+        MsgTypes::Reaction => match (msg.id, msg.data, msg.from) {
+            (Some(id), Some(emoji), Some(from)) => Some(BusEvent::Reaction {
+                id,
+                emoji,
+                from,
+                room,
+            }),
+            _ => None,
+        },
+        MsgTypes::DirectMessage => match (msg.from, msg.to, msg.data) {
+            (Some(from), Some(to), Some(message)) => Some(BusEvent::DirectMessage {
+                to,
+                message: MessageData {
+                    from,
+                    message,
+                    timestamp: Some(msg.timestamp.unwrap_or_else(js_sys::Date::now)),
+                    kind: msg.kind.unwrap_or_default(),
+                    id: msg.id,
+                    room: None,
+                    status: SendStatus::Sent,
+                    edited: false,
+                    reply_to: msg.reply_to,
+                },
+            }),
+            _ => None,
+        },
+        MsgTypes::History => {
+            let mut messages = Vec::new();
+            for raw in msg.data_array.unwrap_or_default() {
+                let mut message_data = serde_json::from_str::<MessageData>(&raw)?;
+                message_data.room.get_or_insert_with(|| room.clone());
+                messages.push(message_data);
+            }
+            Some(BusEvent::History { room, messages })
+        }
+        MsgTypes::Pin => match (msg.id, msg.data.as_deref(), msg.from) {
+            (Some(id), Some(action @ ("pin" | "unpin")), Some(from)) => Some(BusEvent::Pin {
+                id,
+                pinned: action == "pin",
+                from,
+                room,
+            }),
+            _ => None,
+        },
+        MsgTypes::Leave => msg.data.map(BusEvent::Left),
+        MsgTypes::Presence => match (msg.from, msg.data.as_deref()) {
+            (Some(username), Some(status @ ("away" | "active"))) => Some(BusEvent::Presence {
+                username,
+                away: status == "away",
+            }),
+            _ => None,
+        },
+        // Sanity-checked, since a confused server could echo anything.
+        MsgTypes::Pong => msg
+            .timestamp
+            .map(|sent| js_sys::Date::now() - sent)
+            .filter(|rtt| *rtt >= 0.0)
+            .map(BusEvent::Latency),
+        MsgTypes::Ack => msg.id.map(BusEvent::Delivered),
+        MsgTypes::Error => {
+            Some(BusEvent::ServerError(msg.data.unwrap_or_else(|| {
+                "The server reported an error.".to_string()
+            })))
+        }
+        MsgTypes::RegisterResult => match msg.data.as_deref() {
+            None | Some("ok") => None,
+            Some("taken") => Some(BusEvent::RegisterRejected(
+                "That username is already taken.".to_string(),
+            )),
+            Some(reason) => Some(BusEvent::RegisterRejected(reason.to_string())),
+        },
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Everything `queue` would write to a socket that accepts every message.
+    fn flush(queue: &mut OutboundQueue) -> Vec<String> {
+        let mut written = Vec::new();
+        while let Some(s) = queue.front() {
+            written.push(s.to_string());
+            queue.sent();
+        }
+        written
+    }
+
+    #[test]
+    fn queued_messages_flush_in_order_after_reconnect() {
+        let mut queue = OutboundQueue::new(OUTBOUND_QUEUE_CAP);
+        for s in ["a", "b", "c"] {
+            queue.push(s.to_string());
+        }
+        // The socket drops before the first write is confirmed.
+        assert_eq!(queue.front(), Some("a"));
+        queue.push("d".to_string());
+
+        assert_eq!(flush(&mut queue), ["a", "b", "c", "d"]);
+        assert_eq!(queue.front(), None);
+    }
+
+    #[test]
+    fn invalid_json_is_logged_instead_of_forwarded() {
+        let shared = Shared::new();
+
+        let event = route(&shared, "{not json", 1.0);
+        assert!(matches!(event, Some(BusEvent::MalformedMessage)));
+        let failures = shared.failures.borrow();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].payload, "{not json");
+        assert_eq!(failures[0].at, 1.0);
+        assert_eq!(shared.failure_count.get(), 1);
+    }
+
+    #[test]
+    fn malformed_message_payload_is_logged() {
+        let shared = Shared::new();
+        let frame = r#"{"messageType":"message","data":"{\"from\":1}","timestamp":1}"#;
+
+        assert!(matches!(
+            route(&shared, frame, 2.0),
+            Some(BusEvent::MalformedMessage)
+        ));
+        assert_eq!(shared.failures.borrow()[0].payload, frame);
+    }
+
+    #[test]
+    fn valid_frames_are_not_logged() {
+        let shared = Shared::new();
+        let frame = r#"{"messageType":"users","dataArray":["alice","bob"]}"#;
+
+        match route(&shared, frame, 0.0) {
+            Some(BusEvent::Users(users)) => assert_eq!(users.len(), 2),
+            other => panic!("expected a user list, got {:?}", other),
+        }
+        assert!(route(&shared, r#"{"messageType":"register"}"#, 0.0).is_none());
+        assert!(shared.failures.borrow().is_empty());
+        assert_eq!(shared.failure_count.get(), 0);
+    }
+
+    #[test]
+    fn failure_log_keeps_only_the_most_recent() {
+        let shared = Shared::new();
+        for i in 0..PARSE_FAILURE_LOG_CAP + 5 {
+            route(&shared, &format!("bad {}", i), i as f64);
+        }
+
+        let failures = shared.failures.borrow();
+        assert_eq!(failures.len(), PARSE_FAILURE_LOG_CAP);
+        assert_eq!(failures.front().map(|f| f.payload.as_str()), Some("bad 5"));
+        assert_eq!(
+            failures.back().map(|f| f.at),
+            Some((PARSE_FAILURE_LOG_CAP + 4) as f64)
+        );
+        assert_eq!(shared.failure_count.get(), PARSE_FAILURE_LOG_CAP + 5);
+    }
+
+    #[test]
+    fn default_backoff_doubles_up_to_the_cap() {
+        let config = WebsocketServiceConfig::default();
+        let delays: Vec<u32> = (1..=7)
+            .map(|attempt| config.backoff_delay(attempt))
+            .collect();
+        assert_eq!(delays, [1_000, 2_000, 4_000, 8_000, 16_000, 30_000, 30_000]);
+        assert_eq!(config.backoff_delay(u32::MAX), BACKOFF_MAX_MS);
+    }
+
+    #[test]
+    fn custom_config_is_applied() {
+        let config = WebsocketServiceConfig {
+            url: "ws://example.test:9000".to_string(),
+            reconnect: false,
+            backoff_base_ms: 250,
+            backoff_max_ms: 1_500,
+            heartbeat_interval_ms: 5_000,
+            outbound_queue_cap: 3,
+        };
+        let (service, _task) = WebsocketService::unstarted(config.clone());
+
+        assert_eq!(service.config, config);
+        let delays: Vec<u32> = (1..=5)
+            .map(|attempt| service.config.backoff_delay(attempt))
+            .collect();
+        assert_eq!(delays, [250, 500, 1_000, 1_500, 1_500]);
+    }
+
+    /// `connect` itself spawns onto the browser's event loop, so this builds
+    /// the same service without starting its task.
+    #[test]
+    fn offline_service_is_disconnected_and_drops_sends() {
+        let wss = WebsocketService::offline();
+        assert_eq!(wss.state(), ConnectionState::Disconnected);
+        assert!(wss.tx.clone().try_send("hello".to_string()).is_err());
+    }
+
+    #[test]
+    fn connect_reports_the_given_url() {
+        let url = "ws://chat.example.test/socket";
+        let (service, _task) = WebsocketService::unstarted(WebsocketServiceConfig::for_url(url));
+
+        assert_eq!(service.url(), url);
+        assert_eq!(service.state(), ConnectionState::Connecting);
+        assert_eq!(
+            service.config,
+            WebsocketServiceConfig {
+                url: url.to_string(),
+                ..WebsocketServiceConfig::default()
+            }
+        );
+    }
+
+    #[test]
+    fn full_queue_drops_the_oldest_message() {
+        let mut queue = OutboundQueue::new(OUTBOUND_QUEUE_CAP);
+        for i in 0..OUTBOUND_QUEUE_CAP + 2 {
+            queue.push(i.to_string());
+        }
+
+        let written = flush(&mut queue);
+        assert_eq!(written.len(), OUTBOUND_QUEUE_CAP);
+        assert_eq!(written.first().map(String::as_str), Some("2"));
+        assert_eq!(written.last(), Some(&(OUTBOUND_QUEUE_CAP + 1).to_string()));
+    }
+}
@@ -0,0 +1,64 @@
+// Caches the app shell so YewChat opens without a network. The chat itself
+// still needs the WebSocket; the app shows an offline banner until it's back.
+
+const CACHE = 'yewchat-v1';
+
+// Known at build time; anything else same-origin (e.g. webpack chunks) is
+// cached the first time it's fetched.
+const SHELL = [
+    './',
+    'index.html',
+    'yewchat.js',
+    'yewchat_bg.wasm',
+    'notification.wav',
+    'manifest.webmanifest',
+    'icon.svg',
+];
+
+// Third-party scripts the shell can't render without.
+const CACHED_ORIGINS = ['https://cdn.tailwindcss.com'];
+
+self.addEventListener('install', (event) => {
+    event.waitUntil(
+        caches.open(CACHE)
+            .then((cache) => cache.addAll(SHELL))
+            .then(() => self.skipWaiting()),
+    );
+});
+
+self.addEventListener('activate', (event) => {
+    event.waitUntil(
+        caches.keys()
+            .then((keys) => Promise.all(keys.filter((key) => key !== CACHE).map((key) => caches.delete(key))))
+            .then(() => self.clients.claim()),
+    );
+});
+
+// Network first, so a deploy shows up on the next load; the cache is the
+// fallback when offline.
+self.addEventListener('fetch', (event) => {
+    const request = event.request;
+    const url = new URL(request.url);
+    if (request.method !== 'GET') {
+        return;
+    }
+    if (url.origin !== self.location.origin && !CACHED_ORIGINS.includes(url.origin)) {
+        return;
+    }
+    event.respondWith(
+        fetch(request)
+            .then((response) => {
+                const copy = response.clone();
+                caches.open(CACHE).then((cache) => cache.put(request, copy));
+                return response;
+            })
+            .catch(async () => {
+                const cached = await caches.match(request);
+                if (cached) {
+                    return cached;
+                }
+                const shell = request.mode === 'navigate' ? await caches.match('index.html') : undefined;
+                return shell || Response.error();
+            }),
+    );
+});